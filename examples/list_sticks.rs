@@ -0,0 +1,16 @@
+use antrs::node::{self, usb};
+
+fn main() -> Result<(), node::Error> {
+    for stick in usb::list_sticks()? {
+        println!(
+            "bus {:03} address {:03}: {:04x}:{:04x} serial number {}",
+            stick.bus_number,
+            stick.address,
+            stick.vendor_id,
+            stick.product_id,
+            stick.serial_number.as_deref().unwrap_or("unknown"),
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+#![no_main]
+
+use antrs::message::Message;
+use libfuzzer_sys::fuzz_target;
+
+// Decode a stream of messages the way `reader::Publisher` does, skipping a byte after any
+// error. Decoded messages are encoded again, as encoding must handle anything decode produces.
+fuzz_target!(|data: &[u8]| {
+    let mut offset = 0;
+    while offset < data.len() {
+        match Message::decode(&data[offset..]) {
+            Ok((message, len)) => {
+                let _ = message.encode();
+                offset += len;
+            }
+            Err(_) => offset += 1,
+        }
+    }
+});
@@ -31,8 +31,43 @@ pub trait Device: DataProcessor {
     fn as_data_processor(&self) -> Box<dyn DataProcessor + Send>;
 }
 
+/// Device simulated by a node on a transmit channel, e.g. a virtual sensor for testing displays.
+pub trait Transmitter: DataProcessor {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Transmit
+    }
+    fn device_type(&self) -> u8;
+    fn transmission_type(&self) -> u8;
+    fn rf_frequency(&self) -> u8;
+
+    fn channel_period(&self) -> u16;
+
+    /// Page to broadcast in the next channel period, called each time the channel reports
+    /// `EventTX`.
+    fn next_page(&mut self) -> [u8; 8];
+}
+
 pub trait DataProcessor {
     fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error>;
+
+    /// Process data received from a burst transfer on the given channel, reassembled from its
+    /// packets. The data includes any padding the device added to the final packet.
+    fn process_burst(&mut self, _channel: u8, _data: Vec<u8>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Page to send back to the device as acknowledged data, called after each page is
+    /// processed. Used by profiles answering requests from the device, e.g. a weight scale asking
+    /// for the user profile. Not called while another acknowledged transfer is in progress on the
+    /// channel.
+    fn response(&mut self) -> Option<[u8; 8]> {
+        None
+    }
+
+    /// Process an event reported on the channel, e.g. `EventTransferTXCompleted` once a response
+    /// has been acknowledged by the device. Transfer events are only passed on for responses, not
+    /// for data sent with `Node::send_acknowledged`.
+    fn process_event(&mut self, _code: message::MessageCode) {}
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -35,12 +35,16 @@ pub enum MessageID {
     RequestMessage = 0x4d,
     BroadcastData = 0x4e,
     AcknowledgedData = 0x4f,
+    BurstData = 0x50,
     SetChannelID = 0x51,
     Capabilities = 0x54,
     SetChannelLowPrioritySearchTimeout = 0x63,
     EnableExtendedMessages = 0x66,
     LibConfig = 0x6e,
     StartupMessage = 0x6f,
+    AdvancedBurstData = 0x72,
+    ConfigureAdvancedBurst = 0x78,
+    SerialErrorMessage = 0xae,
 }
 
 impl std::fmt::Display for MessageID {
@@ -172,15 +176,82 @@ pub struct DataPayload {
 
 impl DataPayload {
     fn encode(&self, message_id: MessageID) -> Vec<u8> {
-        // TODO: don't panic, handle all variants of data payload
-        let data = self.data.unwrap();
+        // Payloads without data only carry the channel, as extended data follows the data bytes
+        let Some(data) = self.data else {
+            return vec![SYNC, 1, message_id.into(), self.channel];
+        };
 
         let mut result = vec![SYNC, 9, message_id.into(), self.channel];
         result.extend(data.iter());
+
+        let mut flag = ExtendedDataFlag::empty();
+        let mut extended = vec![];
+        if let Some(channel_id) = self.channel_id {
+            flag |= ExtendedDataFlag::CHANNEL_ID;
+            extended.extend(channel_id.device_number.to_le_bytes());
+            extended.push(channel_id.device_type);
+            extended.push(channel_id.transmission_type);
+        }
+        if let Some(rssi) = self.rssi {
+            flag |= ExtendedDataFlag::RSSI;
+            // Padding byte matches what's observed when decoding, see Message::decode
+            extended.extend([rssi.measurement_type, rssi.rssi, rssi.threshold_config, 0]);
+        }
+        if let Some(rx_timestamp) = self.rx_timestamp {
+            flag |= ExtendedDataFlag::RX_TIMESTAMP;
+            extended.extend(rx_timestamp.to_le_bytes());
+        }
+
+        if !flag.is_empty() {
+            result[1] += 1 + extended.len() as u8;
+            result.push(flag.bits());
+            result.extend(extended);
+        }
+
         result
     }
 }
 
+/// Packet of a burst transfer, sent as either a burst or advanced burst data message.
+///
+/// The sequence number is 0 for the first packet of a transfer, then cycles through 1, 2 and 3
+/// for following packets. Burst packets carry 8 bytes of data, while advanced burst packets
+/// carry the configured packet length of 8, 16 or 24 bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BurstDataPayload {
+    pub channel: u8,
+    pub sequence: u8,
+    pub last: bool,
+    pub data: Vec<u8>,
+}
+
+impl BurstDataPayload {
+    fn encode(&self, message_id: MessageID) -> Vec<u8> {
+        let mut channel_sequence = (self.channel & 0x1f) | ((self.sequence & 0x03) << 5);
+        if self.last {
+            channel_sequence |= 0x80;
+        }
+
+        let mut result = vec![
+            SYNC,
+            1 + self.data.len() as u8,
+            message_id.into(),
+            channel_sequence,
+        ];
+        result.extend(&self.data);
+        result
+    }
+
+    fn decode(channel_sequence: u8, data: &[u8]) -> BurstDataPayload {
+        BurstDataPayload {
+            channel: channel_sequence & 0x1f,
+            sequence: (channel_sequence >> 5) & 0x03,
+            last: channel_sequence & 0x80 == 0x80,
+            data: data.to_vec(),
+        }
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq)]
     pub struct CapabilitiesStandardOptions : u8 {
@@ -299,6 +370,51 @@ impl CloseChannelData {
     }
 }
 
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum AdvancedBurstPacketLength {
+    Bytes8 = 0x01,
+    Bytes16 = 0x02,
+    Bytes24 = 0x03,
+}
+
+impl AdvancedBurstPacketLength {
+    pub fn bytes(&self) -> usize {
+        8 * u8::from(*self) as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConfigureAdvancedBurstData {
+    pub enabled: bool,
+    pub max_packet_length: AdvancedBurstPacketLength,
+    /// Features the channel requires of the other end of the burst, 24 bits
+    pub required_features: u32,
+    /// Features used if supported by the other end of the burst, 24 bits
+    pub optional_features: u32,
+}
+
+impl ConfigureAdvancedBurstData {
+    fn encode(&self) -> Vec<u8> {
+        let [required_0, required_1, required_2, _] = self.required_features.to_le_bytes();
+        let [optional_0, optional_1, optional_2, _] = self.optional_features.to_le_bytes();
+        vec![
+            SYNC,
+            9,
+            MessageID::ConfigureAdvancedBurst.into(),
+            0,
+            self.enabled.into(),
+            self.max_packet_length.into(),
+            required_0,
+            required_1,
+            required_2,
+            optional_0,
+            optional_1,
+            optional_2,
+        ]
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct EnableExtendedMessagesData {
     pub enabled: u8,
@@ -498,7 +614,7 @@ impl SetNetworkKeyData {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct StartupMessageData {
-    reason: u8,
+    pub reason: u8,
 }
 
 impl StartupMessageData {
@@ -507,13 +623,45 @@ impl StartupMessageData {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum SerialErrorKind {
+    /// First byte of the message wasn't a sync byte
+    NoSync = 0x00,
+    IncorrectChecksum = 0x02,
+    MessageTooLarge = 0x03,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SerialErrorData {
+    pub error: SerialErrorKind,
+}
+
+impl SerialErrorData {
+    fn encode(&self) -> Vec<u8> {
+        vec![
+            SYNC,
+            1,
+            MessageID::SerialErrorMessage.into(),
+            self.error.into(),
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Error {
     InsufficientData,
+    InvalidAdvancedBurstPacketLength(u8),
     InvalidChannelType(u8),
+    /// Message payload is shorter than required for its message ID
+    InvalidLength {
+        id: MessageID,
+        len: u8,
+    },
     InvalidChecksum,
     InvalidMessageCode(u8),
     InvalidMessageID(u8),
+    InvalidSerialErrorKind(u8),
     InvalidSyncByte,
 }
 
@@ -523,19 +671,23 @@ impl std::fmt::Display for Error {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Message {
     AcknowledgedData(DataPayload),
+    AdvancedBurstData(BurstDataPayload),
     AssignChannel(AssignChannelData),
     BroadcastData(DataPayload),
+    BurstData(BurstDataPayload),
     Capabilities(CapabilitiesData),
     ChannelResponseEvent(ChannelResponseEventData),
     CloseChannel(CloseChannelData),
+    ConfigureAdvancedBurst(ConfigureAdvancedBurstData),
     EnableExtendedMessages(EnableExtendedMessagesData),
     LibConfig(LibConfigData),
     OpenChannel(OpenChannelData),
     RequestMessage(RequestMessageData),
     ResetSystem,
+    SerialError(SerialErrorData),
     SetChannelID(SetChannelIDData),
     SetChannelLowPrioritySearchTimeout(SetChannelLowPrioritySearchTimeoutData),
     SetChannelPeriod(SetChannelPeriodData),
@@ -543,6 +695,11 @@ pub enum Message {
     SetChannelSearchTimeout(SetChannelSearchTimeoutData),
     SetNetworkKey(SetNetworkKeyData),
     StartupMessage(StartupMessageData),
+    /// Message with an ID this library doesn't decode, with its payload as received
+    Unknown {
+        id: u8,
+        payload: Vec<u8>,
+    },
 }
 
 impl std::fmt::Display for Message {
@@ -555,16 +712,20 @@ impl Message {
     pub fn encode(&self) -> Vec<u8> {
         let mut encoded = match self {
             Message::AcknowledgedData(base) => base.encode(MessageID::AcknowledgedData),
+            Message::AdvancedBurstData(base) => base.encode(MessageID::AdvancedBurstData),
             Message::AssignChannel(base) => base.encode(),
             Message::BroadcastData(base) => base.encode(MessageID::BroadcastData),
+            Message::BurstData(base) => base.encode(MessageID::BurstData),
             Message::Capabilities(base) => base.encode(),
             Message::ChannelResponseEvent(base) => base.encode(),
             Message::CloseChannel(base) => base.encode(),
+            Message::ConfigureAdvancedBurst(base) => base.encode(),
             Message::EnableExtendedMessages(base) => base.encode(),
             Message::LibConfig(base) => base.encode(),
             Message::OpenChannel(base) => base.encode(),
             Message::RequestMessage(base) => base.encode(),
             Message::ResetSystem => ResetSystem {}.encode(),
+            Message::SerialError(base) => base.encode(),
             Message::SetChannelID(base) => base.encode(),
             Message::SetChannelLowPrioritySearchTimeout(base) => base.encode(),
             Message::SetChannelPeriod(base) => base.encode(),
@@ -572,6 +733,11 @@ impl Message {
             Message::SetChannelSearchTimeout(base) => base.encode(),
             Message::SetNetworkKey(base) => base.encode(),
             Message::StartupMessage(base) => base.encode(),
+            Message::Unknown { id, payload } => {
+                let mut encoded = vec![SYNC, payload.len() as u8, *id];
+                encoded.extend(payload);
+                encoded
+            }
         };
 
         let mut checksum = 0u8;
@@ -593,17 +759,12 @@ impl Message {
         }
 
         let data_len = data[1];
-        let message_len: usize = (data_len + 4).into();
+        let message_len = usize::from(data_len) + 4;
 
         if data.len() < message_len {
             return Err(Error::InsufficientData);
         }
 
-        let id = match MessageID::try_from(data[2]) {
-            Ok(id) => id,
-            Err(_) => return Err(Error::InvalidMessageID(data[2])),
-        };
-
         let mut calculated: u8 = 0;
         for e in &data[..message_len] {
             calculated ^= *e;
@@ -612,6 +773,21 @@ impl Message {
             return Err(Error::InvalidChecksum);
         }
 
+        let id = match MessageID::try_from(data[2]) {
+            Ok(id) => id,
+            Err(_) => {
+                let message = Message::Unknown {
+                    id: data[2],
+                    payload: data[3..message_len - 1].to_vec(),
+                };
+                return Ok((message, message_len));
+            }
+        };
+
+        if usize::from(data_len) < min_payload_len(id) {
+            return Err(Error::InvalidLength { id, len: data_len });
+        }
+
         let message = match id {
             MessageID::ChannelEvent => return Err(Error::InvalidMessageID(id.into())),
             MessageID::AssignChannel => {
@@ -619,7 +795,12 @@ impl Message {
                     Ok(ct) => ct,
                     Err(_) => return Err(Error::InvalidChannelType(data[4])),
                 };
-                let extended_assignment = ChannelExtendedAssignment::from_bits_retain(data[6]);
+                // Extended assignment is optional
+                let extended_assignment = if data_len >= 4 {
+                    ChannelExtendedAssignment::from_bits_retain(data[6])
+                } else {
+                    ChannelExtendedAssignment::empty()
+                };
                 Message::AssignChannel(AssignChannelData {
                     channel: data[3],
                     channel_type,
@@ -642,7 +823,11 @@ impl Message {
 
                     let mut base = 13usize;
                     let data_len: usize = data_len.into();
-                    let flag = ExtendedDataFlag::from_bits_retain(data[12]);
+                    let flag = if data_len >= 10 {
+                        ExtendedDataFlag::from_bits_retain(data[12])
+                    } else {
+                        ExtendedDataFlag::empty()
+                    };
 
                     if data_len >= 14 && flag.contains(ExtendedDataFlag::CHANNEL_ID) {
                         channel_id = Some(ChannelID {
@@ -705,6 +890,26 @@ impl Message {
                     advanced_options_4,
                 })
             }
+            MessageID::AdvancedBurstData => Message::AdvancedBurstData(BurstDataPayload::decode(
+                data[3],
+                &data[4..message_len - 1],
+            )),
+            // Burst packets may be followed by extended data, which isn't decoded
+            MessageID::BurstData => {
+                Message::BurstData(BurstDataPayload::decode(data[3], &data[4..12]))
+            }
+            MessageID::ConfigureAdvancedBurst => {
+                let max_packet_length: AdvancedBurstPacketLength = match data[5].try_into() {
+                    Ok(length) => length,
+                    Err(_) => return Err(Error::InvalidAdvancedBurstPacketLength(data[5])),
+                };
+                Message::ConfigureAdvancedBurst(ConfigureAdvancedBurstData {
+                    enabled: data[4] != 0,
+                    max_packet_length,
+                    required_features: u32::from_le_bytes([data[6], data[7], data[8], 0]),
+                    optional_features: u32::from_le_bytes([data[9], data[10], data[11], 0]),
+                })
+            }
             MessageID::ChannelResponseEvent => {
                 let message_id: MessageID = match data[4].try_into() {
                     Ok(id) => id,
@@ -740,6 +945,13 @@ impl Message {
                 })
             }
             MessageID::ResetSystem => Message::ResetSystem,
+            MessageID::SerialErrorMessage => {
+                let error: SerialErrorKind = match data[3].try_into() {
+                    Ok(error) => error,
+                    Err(_) => return Err(Error::InvalidSerialErrorKind(data[3])),
+                };
+                Message::SerialError(SerialErrorData { error })
+            }
             MessageID::SetChannelID => {
                 let device = u16::from_le_bytes([data[4], data[5]]);
                 let pairing = (data[6] & 0x80) == 0x80;
@@ -800,6 +1012,36 @@ impl Message {
     }
 }
 
+/// Minimum payload length, excluding the sync, length, ID and checksum bytes, of messages with
+/// the given ID.
+fn min_payload_len(id: MessageID) -> usize {
+    match id {
+        MessageID::ChannelEvent | MessageID::ResetSystem => 0,
+        // Data messages without data are accepted, but must include the channel
+        MessageID::AcknowledgedData
+        | MessageID::BroadcastData
+        | MessageID::CloseChannel
+        | MessageID::OpenChannel
+        | MessageID::SerialErrorMessage
+        | MessageID::StartupMessage => 1,
+        MessageID::EnableExtendedMessages
+        | MessageID::LibConfig
+        | MessageID::RequestMessage
+        | MessageID::SetChannelLowPrioritySearchTimeout
+        | MessageID::SetChannelRFFrequency
+        | MessageID::SetChannelSearchTimeout => 2,
+        MessageID::AssignChannel
+        | MessageID::ChannelResponseEvent
+        | MessageID::SetChannelPeriod => 3,
+        MessageID::SetChannelID => 5,
+        MessageID::Capabilities => 7,
+        MessageID::AdvancedBurstData
+        | MessageID::BurstData
+        | MessageID::ConfigureAdvancedBurst
+        | MessageID::SetNetworkKey => 9,
+    }
+}
+
 pub fn request_data_page(channel: u8, page: u8) -> Message {
     Message::AcknowledgedData(DataPayload {
         channel,
@@ -814,6 +1056,87 @@ pub fn request_data_page(channel: u8, page: u8) -> Message {
 mod test {
     use super::*;
 
+    fn frame(id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![SYNC, payload.len() as u8, id];
+        frame.extend(payload);
+        frame.push(frame.iter().fold(0, |checksum, b| checksum ^ b));
+        frame
+    }
+
+    #[test]
+    fn it_rejects_short_payloads() {
+        assert_eq!(
+            Message::decode(&frame(MessageID::AssignChannel.into(), &[0, 0])),
+            Err(Error::InvalidLength {
+                id: MessageID::AssignChannel,
+                len: 2
+            })
+        );
+        assert_eq!(
+            Message::decode(&frame(MessageID::SetChannelID.into(), &[0, 1, 2, 3])),
+            Err(Error::InvalidLength {
+                id: MessageID::SetChannelID,
+                len: 4
+            })
+        );
+        assert_eq!(
+            Message::decode(&frame(MessageID::Capabilities.into(), &[8, 8, 0, 0, 0, 0])),
+            Err(Error::InvalidLength {
+                id: MessageID::Capabilities,
+                len: 6
+            })
+        );
+    }
+
+    #[test]
+    fn it_decodes_broadcast_data_without_extended_flag() {
+        let payload = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let (message, _) = Message::decode(&frame(MessageID::BroadcastData.into(), &payload))
+            .expect("should decode");
+        assert_eq!(
+            message,
+            Message::BroadcastData(DataPayload {
+                channel: 1,
+                data: Some([2, 3, 4, 5, 6, 7, 8, 9]),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_decodes_arbitrary_input_without_panicking() {
+        // Every message ID with every payload length up to the longest message, so each
+        // variant's length checks are exercised with otherwise valid frames
+        for id in 0..=255u8 {
+            for len in 0..=24u8 {
+                for fill in [0x00, 0xff] {
+                    let payload: Vec<u8> = (0..len).map(|i| fill ^ i).collect();
+                    let _ = Message::decode(&frame(id, &payload));
+                }
+            }
+        }
+
+        // Pseudo-random bytes, decoded from every offset
+        let mut state = 0x2545f491u32;
+        let data: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                if state.is_multiple_of(8) {
+                    SYNC
+                } else {
+                    state as u8
+                }
+            })
+            .collect();
+        for offset in 0..data.len() {
+            let _ = Message::decode(&data[offset..]);
+        }
+    }
+
     #[test]
     fn it_encodes_acknowledged_data() {
         let message = Message::AcknowledgedData(DataPayload {
@@ -877,23 +1200,31 @@ mod test {
     }
 
     #[test]
-    fn it_decodes_broadcast_data_zero_length() {
+    fn it_rejects_broadcast_data_zero_length() {
         let data = vec![0xa4, 0x00, 0x4e, 0xea];
         assert_eq!(
             Message::decode(&data),
-            Ok((
-                Message::BroadcastData(DataPayload {
-                    channel: 0xea,
-                    data: None,
-                    channel_id: None,
-                    rssi: None,
-                    rx_timestamp: None
-                }),
-                4
-            ))
+            Err(Error::InvalidLength {
+                id: MessageID::BroadcastData,
+                len: 0
+            })
         )
     }
 
+    #[test]
+    fn it_encodes_and_decodes_broadcast_data_without_data() {
+        let data = vec![0xa4, 0x01, 0x4e, 0x00, 0xeb];
+        let message = Message::BroadcastData(DataPayload {
+            channel: 0,
+            data: None,
+            channel_id: None,
+            rssi: None,
+            rx_timestamp: None,
+        });
+        assert_eq!(Message::decode(&data), Ok((message.clone(), 5)));
+        assert_eq!(message.encode(), data);
+    }
+
     #[test]
     fn it_decodes_broadcast_data() {
         let data = vec![
@@ -1039,6 +1370,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_encodes_extended_broadcast_data_e0() {
+        let message = Message::BroadcastData(DataPayload {
+            channel: 0,
+            data: Some([0x02, 0x00, 0x16, 0x0e, 0xc7, 0xdc, 0x00, 0x01]),
+            channel_id: Some(ChannelID {
+                device_number: 0x6f53,
+                device_type: 0x23,
+                transmission_type: 0x65,
+            }),
+            rssi: Some(RSSI {
+                measurement_type: 0x10,
+                rssi: 0x01,
+                threshold_config: 0x6d,
+            }),
+            rx_timestamp: Some(0x8461),
+        });
+        assert_eq!(
+            message.encode(),
+            vec![
+                0xa4, 0x14, 0x4e, 0x00, 0x02, 0x00, 0x16, 0x0e, 0xc7, 0xdc, 0x00, 0x01, 0xe0, 0x53,
+                0x6f, 0x23, 0x65, 0x10, 0x01, 0x6d, 0x00, 0x61, 0x84, 0xfd,
+            ]
+        );
+    }
+
     #[test]
     fn it_encodes_capabilities() {
         let message = Message::Capabilities(CapabilitiesData {
@@ -1109,6 +1466,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::useless_conversion)]
     fn it_encodes_channel_response_event() {
         let message = Message::ChannelResponseEvent(ChannelResponseEventData {
             channel: 1,
@@ -1244,6 +1602,126 @@ mod test {
         assert_eq!(Message::decode(&data), Ok((Message::ResetSystem, 5)))
     }
 
+    #[test]
+    fn it_encodes_serial_error() {
+        let message = Message::SerialError(SerialErrorData {
+            error: SerialErrorKind::IncorrectChecksum,
+        });
+        assert_eq!(message.encode(), vec![SYNC, 1, 0xae, 0x02, 0x09]);
+    }
+
+    #[test]
+    fn it_decodes_serial_error() {
+        let data = [SYNC, 1, 0xae, 0x03, 0x08];
+        assert_eq!(
+            Message::decode(&data),
+            Ok((
+                Message::SerialError(SerialErrorData {
+                    error: SerialErrorKind::MessageTooLarge,
+                }),
+                5
+            ))
+        );
+
+        let data = [SYNC, 1, 0xae, 0x07, 0x0c];
+        assert_eq!(
+            Message::decode(&data),
+            Err(Error::InvalidSerialErrorKind(0x07))
+        );
+    }
+
+    #[test]
+    fn it_encodes_burst_data() {
+        let message = Message::BurstData(BurstDataPayload {
+            channel: 1,
+            sequence: 2,
+            last: true,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        });
+        assert_eq!(
+            message.encode(),
+            vec![SYNC, 9, 0x50, 0xc1, 1, 2, 3, 4, 5, 6, 7, 8, 0x34]
+        );
+    }
+
+    #[test]
+    fn it_decodes_burst_data() {
+        let data = [SYNC, 9, 0x50, 0x21, 1, 2, 3, 4, 5, 6, 7, 8, 0xd4];
+        assert_eq!(
+            Message::decode(&data),
+            Ok((
+                Message::BurstData(BurstDataPayload {
+                    channel: 1,
+                    sequence: 1,
+                    last: false,
+                    data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                }),
+                13
+            ))
+        );
+    }
+
+    #[test]
+    fn it_decodes_advanced_burst_data() {
+        let payload: Vec<u8> = (0..17).collect();
+        let (message, _) = Message::decode(&frame(0x72, &payload)).unwrap();
+        assert_eq!(
+            message,
+            Message::AdvancedBurstData(BurstDataPayload {
+                channel: 0,
+                sequence: 0,
+                last: false,
+                data: (1..17).collect(),
+            })
+        );
+        assert_eq!(message.encode(), frame(0x72, &payload));
+    }
+
+    #[test]
+    fn it_encodes_configure_advanced_burst() {
+        let message = Message::ConfigureAdvancedBurst(ConfigureAdvancedBurstData {
+            enabled: true,
+            max_packet_length: AdvancedBurstPacketLength::Bytes24,
+            required_features: 0,
+            optional_features: 0x000001,
+        });
+        assert_eq!(message.encode(), frame(0x78, &[0, 1, 3, 0, 0, 0, 1, 0, 0]));
+        assert_eq!(Message::decode(&message.encode()), Ok((message, 13)));
+
+        assert_eq!(
+            Message::decode(&frame(0x78, &[0, 1, 4, 0, 0, 0, 1, 0, 0])),
+            Err(Error::InvalidAdvancedBurstPacketLength(4))
+        );
+    }
+
+    #[test]
+    fn it_encodes_unknown() {
+        let message = Message::Unknown {
+            id: 0x99,
+            payload: vec![0x01, 0x02],
+        };
+        assert_eq!(message.encode(), vec![SYNC, 2, 0x99, 0x01, 0x02, 0x3c]);
+    }
+
+    #[test]
+    fn it_decodes_unknown() {
+        let data = [SYNC, 2, 0x99, 0x01, 0x02, 0x3c];
+        assert_eq!(
+            Message::decode(&data),
+            Ok((
+                Message::Unknown {
+                    id: 0x99,
+                    payload: vec![0x01, 0x02]
+                },
+                6
+            ))
+        );
+
+        // Checksum is validated before the message ID
+        let data = [SYNC, 2, 0x99, 0x01, 0x02, 0x3d];
+        assert_eq!(Message::decode(&data), Err(Error::InvalidChecksum));
+    }
+
     #[test]
     fn it_encodes_set_channel_id() {
         let message = Message::SetChannelID(SetChannelIDData {
@@ -1,11 +1,28 @@
+use std::time::Duration;
+
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
 pub enum PageNumber {
     CommandStatus = 71,
+    GenericCommand = 73,
+    MultiComponentManufacturerInformation = 78,
+    MultiComponentProductInformation = 79,
     ManufacturerInformation = 80,
     ProductInformation = 81,
+    BatteryStatus = 82,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum BatteryStatus {
+    New = 1,
+    Good = 2,
+    Ok = 3,
+    Low = 4,
+    Critical = 5,
+    Invalid = 7,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -16,6 +33,26 @@ pub enum DataPage {
         command_status: super::CommandStatus,
         response_data: [u8; 4],
     },
+    /// Command sent by a remote control, e.g. an ANT+ Controls remote
+    GenericCommand {
+        serial_number: u16,
+        manufacturer_id: u16,
+        sequence_no: u8,
+        command: u16,
+    },
+    MultiComponentManufacturerInformation {
+        number_of_components: u8,
+        component_identifier: u8,
+        hardware_revision: u8,
+        manufacturer_id: u16,
+        model_number: u16,
+    },
+    MultiComponentProductInformation {
+        number_of_components: u8,
+        component_identifier: u8,
+        software_revision: u16,
+        serial_number: u32,
+    },
     ManufacturerInformation {
         hardware_revision: u8,
         manufacturer_id: u16,
@@ -25,6 +62,23 @@ pub enum DataPage {
         software_revision: u16,
         serial_number: u32,
     },
+    BatteryStatus {
+        /// Number of batteries, and the identifier of the reported battery
+        number_of_batteries: Option<u8>,
+        battery_identifier: Option<u8>,
+        cumulative_operating_time: Duration,
+        /// measured in volts
+        battery_voltage: Option<f64>,
+        battery_status: BatteryStatus,
+    },
+}
+
+fn software_revision(data: [u8; 8]) -> u16 {
+    let mut software_revision = Into::<u16>::into(data[3]) * 100;
+    if data[2] != 0xff {
+        software_revision += Into::<u16>::into(data[2]);
+    }
+    software_revision
 }
 
 pub fn decode(data: [u8; 8]) -> Option<DataPage> {
@@ -40,17 +94,51 @@ pub fn decode(data: [u8; 8]) -> Option<DataPage> {
             manufacturer_id: u16::from_le_bytes([data[4], data[5]]),
             model_number: u16::from_le_bytes([data[6], data[7]]),
         }),
-        Ok(PageNumber::ProductInformation) => {
-            let mut software_revision = Into::<u16>::into(data[3]) * 100;
-            if data[2] != 0xff {
-                software_revision += Into::<u16>::into(data[2]);
-            }
-
-            Some(DataPage::ProductInformation {
-                software_revision,
+        Ok(PageNumber::GenericCommand) => Some(DataPage::GenericCommand {
+            serial_number: u16::from_le_bytes([data[1], data[2]]),
+            manufacturer_id: u16::from_le_bytes([data[3], data[4]]),
+            sequence_no: data[5],
+            command: u16::from_le_bytes([data[6], data[7]]),
+        }),
+        Ok(PageNumber::MultiComponentManufacturerInformation) => {
+            Some(DataPage::MultiComponentManufacturerInformation {
+                number_of_components: data[1] & 0x0f,
+                component_identifier: data[1] >> 4,
+                hardware_revision: data[3],
+                manufacturer_id: u16::from_le_bytes([data[4], data[5]]),
+                model_number: u16::from_le_bytes([data[6], data[7]]),
+            })
+        }
+        Ok(PageNumber::MultiComponentProductInformation) => {
+            Some(DataPage::MultiComponentProductInformation {
+                number_of_components: data[1] & 0x0f,
+                component_identifier: data[1] >> 4,
+                software_revision: software_revision(data),
                 serial_number: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
             })
         }
+        Ok(PageNumber::ProductInformation) => Some(DataPage::ProductInformation {
+            software_revision: software_revision(data),
+            serial_number: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+        }),
+        Ok(PageNumber::BatteryStatus) => {
+            // Operating time is in 2 or 16 second units, depending on bit 7
+            let resolution = if data[7] & 0x80 != 0 { 2 } else { 16 };
+            let operating_time = u32::from_le_bytes([data[3], data[4], data[5], 0]);
+
+            Some(DataPage::BatteryStatus {
+                number_of_batteries: (data[2] != 0xff).then_some(data[2] & 0x0f),
+                battery_identifier: (data[2] != 0xff).then_some(data[2] >> 4),
+                cumulative_operating_time: Duration::from_secs(
+                    u64::from(operating_time) * resolution,
+                ),
+                battery_voltage: match data[7] & 0x0f {
+                    0x0f => None,
+                    coarse => Some(f64::from(coarse) + f64::from(data[6]) / 256.0),
+                },
+                battery_status: BatteryStatus::try_from((data[7] >> 4) & 0x07).ok()?,
+            })
+        }
         Err(_) => None,
     }
 }
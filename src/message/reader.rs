@@ -7,7 +7,6 @@ use std::sync::Mutex;
 #[derive(Debug)]
 pub enum Error {
     ReadError(crate::node::Error),
-    DecodeError(super::Error),
 }
 
 impl From<crate::node::Error> for Error {
@@ -16,12 +15,6 @@ impl From<crate::node::Error> for Error {
     }
 }
 
-impl From<super::Error> for Error {
-    fn from(value: super::Error) -> Self {
-        Self::DecodeError(value)
-    }
-}
-
 struct Buffer {
     data: Vec<u8>,
     read_index: usize,
@@ -32,6 +25,7 @@ pub struct Publisher<'reader> {
     reader: Mutex<&'reader (dyn node::Reader + Sync)>,
     buffer: Mutex<Buffer>,
     sender: crossbeam_channel::Sender<super::Message>,
+    errors: Option<crossbeam_channel::Sender<super::Error>>,
     request_stop: AtomicBool,
 }
 
@@ -40,7 +34,7 @@ impl Publisher<'_> {
         reader: &(dyn node::Reader + Sync),
         sender: crossbeam_channel::Sender<super::Message>,
         buffer_size: usize,
-    ) -> Publisher {
+    ) -> Publisher<'_> {
         Publisher {
             reader: reader.into(),
             sender,
@@ -49,16 +43,28 @@ impl Publisher<'_> {
                 read_index: 0,
                 write_index: 0,
             }),
+            errors: None,
             request_stop: AtomicBool::new(false),
         }
     }
 
+    /// Send the decode error for each dropped frame to `errors`.
+    ///
+    /// Bytes are dropped until the next frame decodes, so one error is sent for each run of
+    /// dropped bytes, even if it contains sync bytes which didn't start a valid frame.
+    pub fn report_errors(mut self, errors: crossbeam_channel::Sender<super::Error>) -> Self {
+        self.errors = Some(errors);
+        self
+    }
+
     pub fn stop(&self) {
         self.request_stop.store(true, Ordering::SeqCst);
     }
 
     pub fn run(&self) -> Result<(), Error> {
         let mut buffer = self.buffer.lock().unwrap();
+        // Whether bytes are being dropped since a frame failed to decode
+        let mut resynchronising = false;
 
         loop {
             if self.request_stop.load(Ordering::SeqCst) {
@@ -81,33 +87,48 @@ impl Publisher<'_> {
             }
 
             if read_size > 0 {
-                let mut discard_count = 0usize;
-                while buffer.data[buffer.read_index] != super::SYNC
-                    && buffer.read_index < buffer.write_index
-                {
-                    buffer.read_index += 1;
-                    discard_count += 1;
-                }
+                loop {
+                    let mut discard_count = 0usize;
+                    while buffer.read_index < buffer.write_index
+                        && buffer.data[buffer.read_index] != super::SYNC
+                    {
+                        buffer.read_index += 1;
+                        discard_count += 1;
+                    }
 
-                if discard_count > 0 {
-                    warn!("discarded {} bytes!", discard_count);
-                }
+                    if discard_count > 0 {
+                        warn!("discarded {} bytes!", discard_count);
+                    }
 
-                while buffer.read_index + 5 < buffer.write_index {
-                    let msg = match super::Message::decode(
+                    if buffer.read_index == buffer.write_index {
+                        break;
+                    }
+
+                    match super::Message::decode(
                         &buffer.data[buffer.read_index..buffer.write_index],
                     ) {
                         Ok((msg, len)) => {
+                            resynchronising = false;
                             buffer.read_index += len;
-                            msg
+                            self.sender.send(msg).expect("send should work");
                         }
                         Err(super::Error::InsufficientData) => {
                             break;
                         }
-                        Err(e) => return Err(e.into()),
-                    };
-
-                    self.sender.send(msg).expect("send should work");
+                        Err(e) => {
+                            // Drop the frame by skipping its sync byte, so decoding resumes from
+                            // the next sync byte found
+                            buffer.read_index += 1;
+                            if !resynchronising {
+                                warn!("dropped frame: {:?}", e);
+                                resynchronising = true;
+                                if let Some(ref errors) = self.errors {
+                                    // The receiver not listening for errors isn't fatal
+                                    let _ = errors.send(e);
+                                }
+                            }
+                        }
+                    }
                 }
 
                 if buffer.read_index == buffer.write_index {
@@ -129,6 +150,7 @@ impl Publisher<'_> {
 }
 
 #[cfg(test)]
+#[allow(clippy::get_first, clippy::len_zero)]
 mod test {
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::{Arc, Mutex};
@@ -196,13 +218,17 @@ mod test {
     }
 
     fn run_test(buffers: Vec<Vec<u8>>) -> Result<Vec<Message>, String> {
+        run_test_counting_dropped(buffers).map(|(messages, _)| messages)
+    }
+
+    fn run_test_counting_dropped(buffers: Vec<Vec<u8>>) -> Result<(Vec<Message>, u64), String> {
         let messages = Arc::new(Mutex::new(vec![]));
         let (sender, receiver) = crossbeam_channel::unbounded();
         let stop = Arc::new(AtomicBool::new(false));
 
         let reader = MockReader::new(buffers);
 
-        thread::scope(|s| {
+        let dropped = thread::scope(|s| {
             let receiver_handle;
             {
                 let stop = Arc::clone(&stop);
@@ -223,7 +249,9 @@ mod test {
                 });
             }
 
-            let publisher = Arc::new(super::Publisher::new(&reader, sender, 128));
+            let (errors, errors_receiver) = crossbeam_channel::unbounded();
+            let publisher =
+                Arc::new(super::Publisher::new(&reader, sender, 128).report_errors(errors));
 
             let publisher_handle;
             {
@@ -253,11 +281,11 @@ mod test {
                 return Err("receiver thread shouldn't panic");
             }
 
-            Ok(())
+            Ok(errors_receiver.try_iter().count() as u64)
         })?;
 
         let messages = messages.lock().unwrap().to_owned();
-        Ok(messages)
+        Ok((messages, dropped))
     }
 
     #[test]
@@ -290,6 +318,24 @@ mod test {
         }
     }
 
+    #[test]
+    fn it_parses_single_short_message() {
+        let buffer = vec![SYNC, 0x01, MessageID::StartupMessage.into(), 0x20, 0xea];
+
+        match run_test(vec![buffer]) {
+            Ok(messages) => {
+                assert_eq!(messages.len(), 1);
+                assert_eq!(
+                    messages.first(),
+                    Some(&Message::StartupMessage(
+                        crate::message::StartupMessageData { reason: 0x20 }
+                    ))
+                )
+            }
+            Err(e) => panic!("test run raised an error: {}", e),
+        }
+    }
+
     #[test]
     fn it_parses_two_messages() {
         let buffer = vec![
@@ -412,4 +458,90 @@ mod test {
             Err(e) => panic!("error returned by test run: {}", e),
         }
     }
+
+    #[test]
+    fn it_resynchronises_after_corrupt_frames() {
+        let response = vec![
+            SYNC,
+            0x03,
+            MessageID::ChannelResponseEvent.into(),
+            0x00,
+            MessageID::SetNetworkKey.into(),
+            MessageCode::ResponseNoError.into(),
+            0xa1,
+        ];
+        let mut buffer = vec![SYNC, 0x01, MessageID::StartupMessage.into(), 0x20, 0x00];
+        buffer.extend(&response);
+        // Channel response event referring to an invalid message ID
+        buffer.extend([SYNC, 0x03, 0x40, 0x00, 0xff, 0x00, 0x18]);
+        buffer.extend(&response);
+
+        let (messages, dropped) = run_test_counting_dropped(vec![buffer]).unwrap();
+        let expected = Message::ChannelResponseEvent(crate::message::ChannelResponseEventData {
+            channel: 0,
+            message_id: MessageID::SetNetworkKey,
+            message_code: MessageCode::ResponseNoError,
+        });
+        assert_eq!(messages, vec![expected.clone(), expected]);
+        assert_eq!(dropped, 2);
+    }
+
+    #[test]
+    fn it_counts_one_dropped_frame_per_resync() {
+        let response = vec![
+            SYNC,
+            0x03,
+            MessageID::ChannelResponseEvent.into(),
+            0x00,
+            MessageID::SetNetworkKey.into(),
+            MessageCode::ResponseNoError.into(),
+            0xa1,
+        ];
+        // Corrupt frame containing a sync byte, which doesn't start a valid frame either
+        let mut buffer = vec![SYNC, 0x03, 0x40, 0x00, SYNC, 0x01, 0x00];
+        buffer.extend(&response);
+
+        let (messages, dropped) = run_test_counting_dropped(vec![buffer]).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(dropped, 1);
+    }
+
+    #[test]
+    fn it_reports_dropped_frames() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let (errors, errors_receiver) = crossbeam_channel::unbounded();
+        let reader = MockReader::new(vec![vec![
+            SYNC,
+            0x01,
+            MessageID::StartupMessage.into(),
+            0x20,
+            0x00,
+        ]]);
+
+        let publisher = super::Publisher::new(&reader, sender, 128).report_errors(errors);
+        thread::scope(|s| {
+            s.spawn(|| publisher.run());
+
+            assert_eq!(
+                errors_receiver.recv_timeout(Duration::from_secs(1)),
+                Ok(crate::message::Error::InvalidChecksum)
+            );
+
+            publisher.stop();
+        });
+    }
+
+    #[test]
+    fn it_publishes_unknown_messages() {
+        let buffer = vec![SYNC, 0x02, 0x99, 0x01, 0x02, 0x3c];
+
+        let messages = run_test(vec![buffer]).unwrap();
+        assert_eq!(
+            messages,
+            vec![Message::Unknown {
+                id: 0x99,
+                payload: vec![0x01, 0x02]
+            }]
+        );
+    }
 }
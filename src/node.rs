@@ -1,30 +1,40 @@
-mod capabilities;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod capabilities;
+pub mod emulator;
+pub mod pool;
+pub mod serial;
+pub mod usb;
 
 use core::time::Duration;
 use log::{error, trace};
-use std::collections::{hash_map, HashMap};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{hash_map, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 
 use crate::device;
 use crate::message::{self, reader, Message, MessageCode, MessageID, RequestMessageData};
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     DeviceNotFound,
     DeviceNotInitialized,
+    NodeNotFound,
     EndpointNotInitialized,
     HandleNotInitialized,
     EndpointNotFound,
     Timeout,
     USBError(rusb::Error),
+    SerialPortError(serialport::ErrorKind),
     ChannelResponseError,
     NoAvailableChannel,
     CapabilitiesNotInitialized,
     ExtendedMessagesNotSupported,
     ChannelDisconnected,
     ChannelInvalidState,
+    TransferFailed(MessageCode),
+    DeviceNumberNotSet,
 }
 
 impl From<rusb::Error> for Error {
@@ -51,19 +61,81 @@ impl std::fmt::Display for Error {
     }
 }
 
-const DYNASTREAM_INNOVATIONS_VID: u16 = 0xfcf;
-const DI_ANT_M_STICK: u16 = 0x1009;
+struct MessageNotifier {
+    matcher: Box<dyn Fn(Message) -> bool + Send>,
+    sender: NotifierSender,
+}
 
-#[derive(Clone, Copy)]
-struct Endpoint {
-    interface: u8,
-    address: u8,
+/// Channel a waiter receives its message on, a tokio oneshot for async waiters so they can await
+/// it without blocking a thread.
+enum NotifierSender {
+    Blocking(crossbeam_channel::Sender<Result<Message, Error>>),
+    #[cfg(feature = "async")]
+    Async(tokio::sync::oneshot::Sender<Result<Message, Error>>),
 }
 
-struct MessageNotifier {
-    id: u64,
-    matcher: Box<dyn Fn(Message) -> bool + Send>,
-    sender: crossbeam_channel::Sender<Message>,
+impl NotifierSender {
+    /// Send the result to the waiter, failing if it has already timed out and dropped its
+    /// receiver.
+    fn send(self, result: Result<Message, Error>) -> Result<(), Result<Message, Error>> {
+        match self {
+            NotifierSender::Blocking(sender) => sender.try_send(result).map_err(|e| e.into_inner()),
+            #[cfg(feature = "async")]
+            NotifierSender::Async(sender) => sender.send(result),
+        }
+    }
+}
+
+/// Sender for events, see [`Node::events`]. Also feeds the event streams of an `AsyncNode`.
+#[derive(Clone)]
+struct EventSender {
+    sender: crossbeam_channel::Sender<Event>,
+    #[cfg(feature = "async")]
+    streams: Arc<Mutex<Vec<tokio::sync::mpsc::Sender<Event>>>>,
+}
+
+impl EventSender {
+    fn send(&self, event: Event) {
+        #[cfg(feature = "async")]
+        self.streams.lock().unwrap().retain(|stream| {
+            !matches!(
+                stream.try_send(event.clone()),
+                Err(tokio::sync::mpsc::error::TrySendError::Closed(_))
+            )
+        });
+        // Sending fails once the buffer is full, when nothing is receiving events
+        let _ = self.sender.try_send(event);
+    }
+
+    /// Receiver for a new event stream, which receives every event sent after it's created.
+    #[cfg(feature = "async")]
+    fn stream(&self) -> tokio::sync::mpsc::Receiver<Event> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(EVENTS_CAPACITY);
+        self.streams.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+/// Number of events buffered for [`Node::events`], further events are dropped until they're
+/// received.
+const EVENTS_CAPACITY: usize = 64;
+
+/// Events reported by a node, see [`Node::events`].
+///
+/// Transport errors also fail any pending `wait_for_message_after` calls, as the messages they're
+/// waiting for may have been lost. Decode errors don't, as a dropped frame rarely holds the
+/// message being waited for, and waiters still time out if it did.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Reading from the transport failed, e.g. because the USB stick was unplugged
+    Disconnected(Error),
+    /// A frame read from the transport couldn't be decoded and was dropped. The node
+    /// resynchronises on the next frame and continues reading.
+    DecodeError(message::Error),
+    /// The transport was reopened after a disconnect, and the network key and channels restored
+    Reconnected,
+    /// The transport was reopened after a disconnect, but restoring the node failed
+    ReconnectFailed(Error),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -74,10 +146,55 @@ pub enum ChannelStatus {
     Closed,
 }
 
+enum ChannelConfiguration {
+    Device(Box<dyn device::Device + Send>, Option<ChannelOptions>),
+    Search(Option<ChannelOptions>),
+    Transmitter(TransmitChannel),
+}
+
+/// Configuration of a channel transmitting as a device.
+#[derive(Clone, Copy)]
+struct TransmitChannel {
+    channel_type: message::ChannelType,
+    channel_id: message::ChannelID,
+    rf_frequency: u8,
+    channel_period: u16,
+}
+
 struct ChannelAssignment {
     device: Option<Box<dyn device::DataProcessor + Send>>,
+    transmitter: Option<Box<dyn device::Transmitter + Send>>,
+    configuration: Option<ChannelConfiguration>,
     status: ChannelStatus,
     events: Vec<MessageCode>,
+    burst: Option<BurstReceive>,
+    /// Whether a response from the processor is being transferred, so that transfer events are
+    /// only passed to the processor for its own responses
+    response_pending: bool,
+}
+
+impl ChannelAssignment {
+    /// Processor for data received on the channel, from the device or the transmitter assigned.
+    fn processor(&mut self) -> Option<&mut dyn device::DataProcessor> {
+        match (&mut self.device, &mut self.transmitter) {
+            (Some(device), _) => Some(device.as_mut()),
+            (None, Some(transmitter)) => Some(transmitter.as_mut()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Burst transfer being received on a channel.
+struct BurstReceive {
+    next_sequence: u8,
+    data: Vec<u8>,
+}
+
+/// Device assigned to a channel, along with the options the channel was opened with.
+pub struct AssignedDevice {
+    pub channel: u8,
+    pub device: Box<dyn device::Device + Send>,
+    pub options: Option<ChannelOptions>,
 }
 
 /// Options to configure opened channels.
@@ -87,6 +204,7 @@ struct ChannelAssignment {
 /// both with search timeouts of 10 seconds, the first channel will close with search timeout
 /// after 10 seconds, and the second after 20 seconds (10 seconds after the first closed and the
 /// second entered search).
+#[derive(Clone, Copy, Debug, Default)]
 pub struct ChannelOptions {
     /// Timeout for low priority device search in 2.5 seconds increments, with special cases of
     /// 0 meaning no low priority search and 255 meaning no timeout. If not specified, the device
@@ -104,57 +222,25 @@ pub struct ChannelOptions {
 pub struct Node {
     capabilities: Option<capabilities::Capabilities>,
     network_key: [u8; 8],
-    vendor_id: u16,
-    product_id: u16,
-    device: Option<rusb::Device<rusb::GlobalContext>>,
-    handle: Arc<RwLock<Option<rusb::DeviceHandle<rusb::GlobalContext>>>>,
-    in_ep: Option<Endpoint>,
-    out_ep: Option<Endpoint>,
-    notifiers: Arc<Mutex<Vec<MessageNotifier>>>,
+    link: Link,
     assigned: Arc<RwLock<HashMap<u8, Mutex<ChannelAssignment>>>>,
+    events: (EventSender, crossbeam_channel::Receiver<Event>),
+    reconnect_interval: Option<Duration>,
+    closed: Arc<AtomicBool>,
+    dropped_frames: Arc<AtomicU64>,
+    advanced_burst: Option<message::AdvancedBurstPacketLength>,
+    acknowledged_retries: u8,
+    device_number: Option<u16>,
 }
 
 impl Node {
     pub fn open(&mut self) -> Result<(), Error> {
-        self.device = Some(self.find_device()?);
-
-        let (in_ep, out_ep) = self.find_endpoints()?;
-        self.in_ep = Some(in_ep);
-        self.out_ep = Some(out_ep);
-
-        let mut handle = self
-            .device
-            .clone()
-            .ok_or(Error::DeviceNotInitialized)?
-            .open()?;
-
-        handle.set_auto_detach_kernel_driver(true)?;
-        handle.set_active_configuration(0)?;
-        handle.claim_interface(in_ep.interface)?;
-        if in_ep.interface != out_ep.interface {
-            handle.claim_interface(out_ep.interface)?;
-        }
-
-        {
-            let mut h = self.handle.write().unwrap();
-            *h = Some(handle);
-        }
+        self.closed.store(false, Ordering::SeqCst);
+        self.link.transport.open()?;
 
         self.receive_messages()?;
 
-        self.write_message(Message::ResetSystem, Duration::from_millis(100))?;
-        thread::sleep(Duration::from_millis(2000));
-
-        let set_network_key = Message::SetNetworkKey(message::SetNetworkKeyData {
-            network: 0,
-            key: self.network_key,
-        });
-        self.expect_channel_response_no_error_after(
-            0,
-            MessageID::SetNetworkKey,
-            Duration::from_millis(1000),
-            || self.write_message(set_network_key, Duration::from_millis(100)),
-        )?;
+        self.link.initialize(self.network_key)?;
 
         let request_capabilities = Message::RequestMessage(RequestMessageData {
             channel: 0,
@@ -180,12 +266,68 @@ impl Node {
             self.close_channel(channel)?;
         }
 
-        let mut handle = self.handle.write().unwrap();
-        if let Some(ref mut handle) = *handle {
-            handle.reset()?;
-        }
+        self.closed.store(true, Ordering::SeqCst);
+        self.link.transport.reset()
+    }
 
-        Ok(())
+    pub fn capabilities(&self) -> Option<&capabilities::Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Receiver for events such as the node being disconnected. Receivers are clones of each
+    /// other, so each event is only received by one of them. Events are buffered up to a limit,
+    /// after which new events are dropped until buffered events are received.
+    pub fn events(&self) -> crossbeam_channel::Receiver<Event> {
+        self.events.1.clone()
+    }
+
+    /// Number of frames received from the transport which were dropped because they couldn't be
+    /// decoded. Bytes dropped until the next frame decodes are counted as a single frame.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Number of channels which are not currently assigned.
+    pub fn available_channels(&self) -> Result<u8, Error> {
+        let capabilities = self
+            .capabilities
+            .as_ref()
+            .ok_or(Error::CapabilitiesNotInitialized)?;
+        let assigned = self.assigned.read().unwrap();
+        Ok(capabilities
+            .max_channels
+            .saturating_sub(assigned.len() as u8))
+    }
+
+    /// Remove every channel assignment without closing the channels, returning the devices
+    /// assigned to channels which haven't closed so they can be assigned again, e.g. to another
+    /// node after this node's device has been disconnected.
+    ///
+    /// Search channels have no device and are dropped.
+    pub fn take_assigned_devices(&mut self) -> Vec<AssignedDevice> {
+        let mut assigned = self.assigned.write().unwrap();
+
+        let mut devices: Vec<AssignedDevice> = assigned
+            .drain()
+            .filter_map(|(channel, assignment)| {
+                let assignment = assignment.into_inner().unwrap();
+                match assignment.configuration {
+                    Some(ChannelConfiguration::Device(device, options))
+                        if assignment.status != ChannelStatus::Closed =>
+                    {
+                        Some(AssignedDevice {
+                            channel,
+                            device,
+                            options,
+                        })
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        devices.sort_by_key(|d| d.channel);
+
+        devices
     }
 
     pub fn close_channel(&mut self, channel: u8) -> Result<(), Error> {
@@ -208,7 +350,7 @@ impl Node {
                     }),
                     Duration::from_secs(1),
                     || {
-                        self.expect_channel_response_no_error_after(
+                        self.link.expect_channel_response_no_error_after(
                             channel,
                             MessageID::CloseChannel,
                             Duration::from_millis(1000),
@@ -260,110 +402,40 @@ impl Node {
         options: Option<ChannelOptions>,
     ) -> Result<(u8, crossbeam_channel::Receiver<message::ChannelID>), Error> {
         let (search, receiver) = device::Search::new();
+        let channel = self.assign_search(Box::new(search), options)?;
 
-        let channel = self._assign_channel(Box::new(search))?;
-
-        let enable_extended_messages =
-            Message::EnableExtendedMessages(message::EnableExtendedMessagesData { enabled: 1 });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::EnableExtendedMessages,
-            Duration::from_millis(100),
-            || self.write_message(enable_extended_messages, Duration::from_millis(100)),
-        )?;
+        Ok((channel, receiver))
+    }
 
-        let assign_channel = Message::AssignChannel(message::AssignChannelData {
-            channel,
-            channel_type: message::ChannelType::Receive,
-            network: 0,
-            extended_assignment: message::ChannelExtendedAssignment::BACKGROUND_SCANNING,
-        });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::AssignChannel,
-            Duration::from_millis(100),
-            || self.write_message(assign_channel, Duration::from_millis(100)),
-        )?;
+    fn assign_search(
+        &mut self,
+        search: Box<dyn device::DataProcessor + Send>,
+        options: Option<ChannelOptions>,
+    ) -> Result<u8, Error> {
+        let channel = self._assign_channel(Some(search))?;
 
-        let set_channel_id = Message::SetChannelID(message::SetChannelIDData {
-            channel,
-            device: 0,
-            pairing: false,
-            device_type: 0,
-            transmission_type: 0,
-        });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::SetChannelID,
-            Duration::from_millis(100),
-            || self.write_message(set_channel_id, Duration::from_secs(100)),
-        )?;
+        let result = self.link.configure_search(channel, options);
+        self.unassign_on_error(channel, result)?;
 
-        let set_channel_period = Message::SetChannelPeriod(message::SetChannelPeriodData {
-            channel,
-            period: 8070,
+        self.update_assignment(channel, |assignment| {
+            assignment.configuration = Some(ChannelConfiguration::Search(options));
         });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::SetChannelPeriod,
-            Duration::from_millis(100),
-            || self.write_message(set_channel_period, Duration::from_millis(100)),
-        )?;
-
-        let set_channel_rf_freq =
-            Message::SetChannelRFFrequency(message::SetChannelRFFrequencyData {
-                channel,
-                frequency: 57,
-            });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::SetChannelRFFrequency,
-            Duration::from_millis(100),
-            || self.write_message(set_channel_rf_freq, Duration::from_millis(100)),
-        )?;
 
-        if let Some(options) = options {
-            if let Some(timeout) = options.low_priority_search_timeout {
-                let search_timeout = Message::SetChannelLowPrioritySearchTimeout(
-                    message::SetChannelLowPrioritySearchTimeoutData { channel, timeout },
-                );
-                self.expect_channel_response_no_error_after(
-                    channel,
-                    MessageID::SetChannelLowPrioritySearchTimeout,
-                    Duration::from_millis(100),
-                    || self.write_message(search_timeout, Duration::from_millis(100)),
-                )?;
-            }
+        Ok(channel)
+    }
 
-            if let Some(timeout) = options.search_timeout {
-                let search_timeout =
-                    Message::SetChannelSearchTimeout(message::SetChannelSearchTimeoutData {
-                        channel,
-                        timeout,
-                    });
-                self.expect_channel_response_no_error_after(
-                    channel,
-                    MessageID::SetChannelSearchTimeout,
-                    Duration::from_millis(100),
-                    || self.write_message(search_timeout, Duration::from_millis(100)),
-                )?;
-            }
+    /// Remove the channel's assignment if configuring it failed, so the channel can be assigned
+    /// again.
+    fn unassign_on_error<T>(&self, channel: u8, result: Result<T, Error>) -> Result<T, Error> {
+        if result.is_err() {
+            self.assigned.write().unwrap().remove(&channel);
         }
-
-        let open_channel = Message::OpenChannel(message::OpenChannelData { channel });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::OpenChannel,
-            Duration::from_millis(100),
-            || self.write_message(open_channel, Duration::from_millis(100)),
-        )?;
-
-        Ok((channel, receiver))
+        result
     }
 
     fn _assign_channel(
         &mut self,
-        processor: Box<dyn device::DataProcessor + Send>,
+        processor: Option<Box<dyn device::DataProcessor + Send>>,
     ) -> Result<u8, Error> {
         let max_channels;
 
@@ -378,8 +450,12 @@ impl Node {
             if let hash_map::Entry::Vacant(e) = assigned.entry(i) {
                 e.insert(Mutex::new(ChannelAssignment {
                     status: ChannelStatus::Assigned,
-                    device: Some(processor),
+                    device: processor,
+                    transmitter: None,
+                    configuration: None,
                     events: Vec::new(),
+                    burst: None,
+                    response_pending: false,
                 }));
                 return Ok(i);
             }
@@ -393,75 +469,826 @@ impl Node {
         device: Box<dyn device::Device + Send>,
         options: Option<ChannelOptions>,
     ) -> Result<u8, Error> {
-        let channel = self._assign_channel(device.as_data_processor())?;
+        let channel = self._assign_channel(Some(device.as_data_processor()))?;
 
-        let assign_channel = Message::AssignChannel(message::AssignChannelData {
-            channel,
-            channel_type: device.channel_type(),
-            network: 0,
-            extended_assignment: message::ChannelExtendedAssignment::empty(),
+        let result = self
+            .link
+            .configure_device(channel, device.as_ref(), options);
+        self.unassign_on_error(channel, result)?;
+
+        self.update_assignment(channel, |assignment| {
+            assignment.status = ChannelStatus::Open;
+            assignment.configuration = Some(ChannelConfiguration::Device(device, options));
         });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::AssignChannel,
-            Duration::from_millis(100),
-            || self.write_message(assign_channel, Duration::from_millis(100)),
-        )?;
 
-        let pairing = device.pairing();
-        let set_channel_id = Message::SetChannelID(message::SetChannelIDData {
-            channel,
-            device: pairing.device_id,
-            pairing: false,
-            device_type: device.device_type(),
-            transmission_type: pairing.transmission_type,
+        Ok(channel)
+    }
+
+    /// Assign a channel to transmit as the given device, using the node's device number set with
+    /// [`NodeBuilder::device_number`] in the channel ID.
+    ///
+    /// The transmitter supplies the page broadcast in each channel period, and processes data
+    /// sent to it by receiving devices, e.g. acknowledged commands from a display.
+    pub fn assign_transmitter(
+        &mut self,
+        transmitter: Box<dyn device::Transmitter + Send>,
+    ) -> Result<u8, Error> {
+        let device_number = self.device_number.ok_or(Error::DeviceNumberNotSet)?;
+        let configuration = TransmitChannel {
+            channel_type: transmitter.channel_type(),
+            channel_id: message::ChannelID {
+                device_number,
+                device_type: transmitter.device_type(),
+                transmission_type: transmitter.transmission_type(),
+            },
+            rf_frequency: transmitter.rf_frequency(),
+            channel_period: transmitter.channel_period(),
+        };
+
+        let channel = self._assign_channel(None)?;
+        self.update_assignment(channel, |assignment| {
+            assignment.transmitter = Some(transmitter);
         });
 
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::SetChannelID,
-            Duration::from_millis(100),
-            || self.write_message(set_channel_id, Duration::from_secs(100)),
-        )?;
+        let result = self.link.configure_transmitter(channel, configuration);
+        self.unassign_on_error(channel, result)?;
 
-        let set_channel_period = Message::SetChannelPeriod(message::SetChannelPeriodData {
-            channel,
-            period: device.channel_period(),
+        self.update_assignment(channel, |assignment| {
+            assignment.status = ChannelStatus::Open;
+            assignment.configuration = Some(ChannelConfiguration::Transmitter(configuration));
         });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::SetChannelPeriod,
+
+        Ok(channel)
+    }
+
+    /// Configure advanced burst for the node. While enabled, bursts sent with
+    /// [`Node::send_burst`] use advanced burst packets of up to the configured packet length.
+    pub fn configure_advanced_burst(
+        &mut self,
+        data: message::ConfigureAdvancedBurstData,
+    ) -> Result<(), Error> {
+        self.link.expect_channel_response_no_error_after(
+            0,
+            MessageID::ConfigureAdvancedBurst,
             Duration::from_millis(100),
-            || self.write_message(set_channel_period, Duration::from_millis(100)),
+            || {
+                self.write_message(
+                    Message::ConfigureAdvancedBurst(data),
+                    Duration::from_millis(100),
+                )
+            },
         )?;
 
-        let set_channel_rf_freq =
-            Message::SetChannelRFFrequency(message::SetChannelRFFrequencyData {
-                channel,
-                frequency: device.rf_frequency(),
-            });
-        self.expect_channel_response_no_error_after(
-            channel,
-            MessageID::SetChannelRFFrequency,
-            Duration::from_millis(100),
-            || self.write_message(set_channel_rf_freq, Duration::from_millis(100)),
+        self.advanced_burst = if data.enabled {
+            Some(data.max_packet_length)
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+
+    /// Send data to the device on a channel as a burst transfer, waiting for the transfer to
+    /// complete. The final packet is padded with zeros.
+    pub fn send_burst(&self, channel: u8, data: &[u8]) -> Result<(), Error> {
+        let packets = burst_packets(channel, data, self.advanced_burst);
+        let timeout = Duration::from_secs(1) + Duration::from_millis(10) * packets.len() as u32;
+
+        let message = self.wait_for_message_after(
+            Box::new(move |message| match message {
+                Message::ChannelResponseEvent(data) if data.channel == channel => match data
+                    .message_id
+                {
+                    MessageID::ChannelEvent => matches!(
+                        data.message_code,
+                        MessageCode::EventTransferTXCompleted | MessageCode::EventTransferTXFailed
+                    ),
+                    MessageID::BurstData | MessageID::AdvancedBurstData => true,
+                    _ => false,
+                },
+                _ => false,
+            }),
+            timeout,
+            || {
+                packets
+                    .into_iter()
+                    .try_for_each(|packet| self.write_message(packet, Duration::from_millis(100)))
+            },
         )?;
 
-        if let Some(options) = options {
-            if let Some(timeout) = options.low_priority_search_timeout {
-                let search_timeout = Message::SetChannelLowPrioritySearchTimeout(
-                    message::SetChannelLowPrioritySearchTimeoutData { channel, timeout },
-                );
-                self.expect_channel_response_no_error_after(
-                    channel,
-                    MessageID::SetChannelLowPrioritySearchTimeout,
-                    Duration::from_millis(100),
-                    || self.write_message(search_timeout, Duration::from_millis(100)),
-                )?;
+        match message {
+            Message::ChannelResponseEvent(data)
+                if data.message_code == MessageCode::EventTransferTXCompleted =>
+            {
+                Ok(())
             }
+            Message::ChannelResponseEvent(data) => Err(Error::TransferFailed(data.message_code)),
+            _ => unreachable!(),
+        }
+    }
 
-            if let Some(timeout) = options.search_timeout {
-                let search_timeout =
+    /// Send an acknowledged data payload to the device on a channel, waiting for the device to
+    /// acknowledge it. Failed transfers are retried up to the number of times configured with
+    /// [`NodeBuilder::acknowledged_retries`], returning `Error::TransferFailed` if every attempt
+    /// fails.
+    pub fn send_acknowledged(&self, channel: u8, payload: [u8; 8]) -> Result<(), Error> {
+        self.link
+            .send_acknowledged(channel, payload, self.acknowledged_retries)
+    }
+
+    fn update_assignment<F: FnOnce(&mut ChannelAssignment)>(&self, channel: u8, update: F) {
+        let assigned = self.assigned.read().unwrap();
+        let assignment = assigned
+            .get(&channel)
+            .expect("should contain new assignment");
+        let mut assignment = assignment.lock().unwrap();
+        (update)(&mut assignment);
+    }
+
+    pub fn wait_for_message_after<T, F: FnOnce() -> Result<T, Error>>(
+        &self,
+        matcher: Box<dyn Fn(Message) -> bool + Send>,
+        timeout: Duration,
+        after: F,
+    ) -> Result<Message, Error> {
+        self.link.wait_for_message_after(matcher, timeout, after)
+    }
+
+    fn receive_messages(&self) -> Result<(), Error> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let (failures, failures_rx) = crossbeam_channel::unbounded();
+        let (decode_errors, decode_errors_rx) = crossbeam_channel::unbounded();
+
+        let link = self.link.clone();
+        let assigned = Arc::clone(&self.assigned);
+        let network_key = self.network_key;
+        let events = self.events.0.clone();
+        let closed = Arc::clone(&self.closed);
+        let reconnect_interval = self.reconnect_interval;
+
+        thread::spawn(move || loop {
+            let reader = TransportReader {
+                transport: Arc::clone(&link.transport),
+            };
+            let publisher = reader::Publisher::new(&reader, tx.clone(), 4096)
+                .report_errors(decode_errors.clone());
+            let Err(reader::Error::ReadError(e)) = publisher.run() else {
+                break;
+            };
+
+            if closed.load(Ordering::SeqCst) {
+                break;
+            }
+
+            error!("error reading from transport: {:?}", e);
+            // Sending only fails once the receiving thread has exited
+            let _ = failures.send(e.clone());
+            events.send(Event::Disconnected(e));
+
+            match reconnect_interval {
+                Some(interval) => {
+                    if !reconnect(&link, interval, &closed) {
+                        break;
+                    }
+
+                    // Restoring the node needs this thread to keep publishing responses
+                    let link = link.clone();
+                    let assigned = Arc::clone(&assigned);
+                    let events = events.clone();
+                    thread::spawn(move || {
+                        let event = match restore(&link, network_key, &assigned) {
+                            Ok(()) => Event::Reconnected,
+                            Err(e) => Event::ReconnectFailed(e),
+                        };
+                        events.send(event);
+                    });
+                }
+                None => break,
+            }
+        });
+
+        let assigned = Arc::clone(&self.assigned);
+        let link = self.link.clone();
+        let events = self.events.0.clone();
+        let dropped_frames = Arc::clone(&self.dropped_frames);
+
+        thread::spawn(move || {
+            loop {
+                crossbeam_channel::select! {
+                    recv(rx) -> message => match message {
+                        Ok(message) => process_message(&assigned, &link, message),
+                        Err(_) => break,
+                    },
+                    recv(failures_rx) -> failure => match failure {
+                        Ok(error) => {
+                            // Messages published before the failure are handled first, so their
+                            // waiters receive them rather than the error
+                            for message in rx.try_iter() {
+                                process_message(&assigned, &link, message);
+                            }
+                            fail_waiters(&link.notifiers, error);
+                        }
+                        Err(_) => break,
+                    },
+                    recv(decode_errors_rx) -> decode_error => if let Ok(e) = decode_error {
+                        for message in rx.try_iter() {
+                            process_message(&assigned, &link, message);
+                        }
+                        // Waiters are left waiting, as their response may still follow
+                        dropped_frames.fetch_add(1, Ordering::Relaxed);
+                        events.send(Event::DecodeError(e));
+                    },
+                }
+            }
+
+            error!("error receiving from publisher");
+            for message in rx.try_iter() {
+                process_message(&assigned, &link, message);
+            }
+            for error in failures_rx.try_iter() {
+                fail_waiters(&link.notifiers, error);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+        self.link.transport.read(buf, timeout)
+    }
+
+    pub fn write_message(&self, message: message::Message, timeout: Duration) -> Result<(), Error> {
+        self.link.write_message(message, timeout)
+    }
+
+    pub fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+        self.link.transport.write(buf, timeout)
+    }
+}
+
+fn process_message(
+    assigned: &RwLock<HashMap<u8, Mutex<ChannelAssignment>>>,
+    link: &Link,
+    message: Message,
+) {
+    trace!("received: {}", message);
+
+    let notifiers = &link.notifiers;
+    match message {
+        Message::BroadcastData(data) | Message::AcknowledgedData(data) => {
+            let channel = data.channel;
+            let mut response = None;
+            {
+                let assigned = assigned.read().unwrap();
+                if let Some(assignment) = assigned.get(&channel) {
+                    let mut assignment = assignment.lock().unwrap();
+                    // Only one acknowledged transfer can be in progress on a channel
+                    let respond = !assignment.response_pending
+                        && !link.sending.lock().unwrap().contains(&channel);
+                    if let Some(processor) = assignment.processor() {
+                        if let Err(e) = processor.process_data(data) {
+                            error!("Error processing data: {:?}", e);
+                        }
+                        if respond {
+                            response = processor.response();
+                        }
+                    }
+                    if response.is_some() {
+                        assignment.response_pending = true;
+                    }
+                }
+            }
+
+            if let Some(page) = response {
+                let acknowledged = Message::AcknowledgedData(message::DataPayload {
+                    channel,
+                    data: Some(page),
+                    channel_id: None,
+                    rssi: None,
+                    rx_timestamp: None,
+                });
+                if let Err(e) = link.write_message(acknowledged, Duration::from_millis(100)) {
+                    error!("failed to write response page: {:?}", e);
+                    // No event will be reported for the transfer, so the processor is told it
+                    // failed, e.g. to send the response again
+                    let assigned = assigned.read().unwrap();
+                    if let Some(assignment) = assigned.get(&channel) {
+                        let mut assignment = assignment.lock().unwrap();
+                        assignment.response_pending = false;
+                        if let Some(processor) = assignment.processor() {
+                            processor.process_event(MessageCode::EventTransferTXFailed);
+                        }
+                    }
+                }
+            }
+        }
+        Message::ChannelResponseEvent(data) => {
+            let mut page = None;
+            if data.message_id == MessageID::ChannelEvent {
+                let assigned = assigned.read().unwrap();
+                if let Some(assignment) = assigned.get(&data.channel) {
+                    let mut assignment = assignment.lock().unwrap();
+                    match data.message_code {
+                        MessageCode::EventChannelClosed => {
+                            assignment.status = ChannelStatus::Closed;
+                            assignment.device = None;
+                            assignment.transmitter = None;
+                            assignment.configuration = None;
+                            assignment.response_pending = false;
+                        }
+                        MessageCode::EventTransferRXFailed => {
+                            assignment.burst = None;
+                        }
+                        MessageCode::EventTX => {
+                            page = assignment.transmitter.as_mut().map(|t| t.next_page());
+                        }
+                        _ => {}
+                    }
+                    let transfer_event = matches!(
+                        data.message_code,
+                        MessageCode::EventTransferTXCompleted | MessageCode::EventTransferTXFailed
+                    );
+                    // Transfer events for data sent with `send_acknowledged` aren't passed on,
+                    // only those completing a response
+                    if !transfer_event || std::mem::take(&mut assignment.response_pending) {
+                        if let Some(processor) = assignment.processor() {
+                            processor.process_event(data.message_code);
+                        }
+                    }
+                    assignment.events.push(data.message_code);
+                }
+            } else if data.message_id == MessageID::AcknowledgedData
+                && data.message_code != MessageCode::ResponseNoError
+                && !link.sending.lock().unwrap().contains(&data.channel)
+            {
+                // The response was rejected, e.g. while another transfer was in progress
+                let assigned = assigned.read().unwrap();
+                if let Some(assignment) = assigned.get(&data.channel) {
+                    let mut assignment = assignment.lock().unwrap();
+                    if assignment.response_pending {
+                        assignment.response_pending = false;
+                        if let Some(processor) = assignment.processor() {
+                            processor.process_event(MessageCode::EventTransferTXFailed);
+                        }
+                    }
+                }
+            }
+
+            if let Some(page) = page {
+                let broadcast = Message::BroadcastData(message::DataPayload {
+                    channel: data.channel,
+                    data: Some(page),
+                    channel_id: None,
+                    rssi: None,
+                    rx_timestamp: None,
+                });
+                if let Err(e) = link.write_message(broadcast, Duration::from_millis(100)) {
+                    error!("failed to write transmitter page: {:?}", e);
+                }
+            }
+            notify_waiters(notifiers, message);
+        }
+        Message::BurstData(data) | Message::AdvancedBurstData(data) => {
+            let assigned = assigned.read().unwrap();
+            if let Some(assignment) = assigned.get(&data.channel) {
+                let mut assignment = assignment.lock().unwrap();
+                receive_burst_packet(&mut assignment, data);
+            }
+        }
+        Message::SerialError(data) => {
+            error!("serial error reported by node: {:?}", data.error);
+            notify_waiters(notifiers, message);
+        }
+        _ => {
+            notify_waiters(notifiers, message);
+        }
+    }
+}
+
+/// Add a packet to the burst being received on a channel, passing the data to the channel's
+/// processor once the last packet is received. Packets out of sequence abandon the transfer.
+fn receive_burst_packet(assignment: &mut ChannelAssignment, packet: message::BurstDataPayload) {
+    let burst = match assignment.burst.take() {
+        _ if packet.sequence == 0 => BurstReceive {
+            next_sequence: 0,
+            data: vec![],
+        },
+        Some(burst) if burst.next_sequence == packet.sequence => burst,
+        _ => {
+            error!(
+                "burst packet out of sequence on channel {}, abandoning transfer",
+                packet.channel
+            );
+            return;
+        }
+    };
+
+    let mut data = burst.data;
+    data.extend(packet.data);
+
+    if packet.last {
+        if let Some(processor) = assignment.processor() {
+            if let Err(e) = processor.process_burst(packet.channel, data) {
+                error!("Error processing burst: {:?}", e);
+            }
+        }
+    } else {
+        assignment.burst = Some(BurstReceive {
+            next_sequence: next_burst_sequence(packet.sequence),
+            data,
+        });
+    }
+}
+
+/// Sequence number of the burst packet following one with the given sequence number. The first
+/// packet of a transfer has sequence 0, and following packets cycle through 1, 2 and 3.
+fn next_burst_sequence(sequence: u8) -> u8 {
+    if sequence == 3 {
+        1
+    } else {
+        sequence + 1
+    }
+}
+
+/// Split data into the packets of a burst transfer, using advanced burst packets if a packet
+/// length is given.
+fn burst_packets(
+    channel: u8,
+    data: &[u8],
+    advanced: Option<message::AdvancedBurstPacketLength>,
+) -> Vec<Message> {
+    let packet_len = advanced.map_or(8, |length| length.bytes());
+    let chunks: Vec<&[u8]> = if data.is_empty() {
+        vec![&[]]
+    } else {
+        data.chunks(packet_len).collect()
+    };
+
+    let mut sequence = 0;
+    let count = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut packet_data = chunk.to_vec();
+            // Advanced burst packets may be shorter than the packet length, but must still
+            // be a multiple of 8 bytes
+            packet_data.resize(packet_data.len().div_ceil(8).max(1) * 8, 0);
+
+            let payload = message::BurstDataPayload {
+                channel,
+                sequence,
+                last: i == count - 1,
+                data: packet_data,
+            };
+            sequence = next_burst_sequence(sequence);
+
+            match advanced {
+                Some(_) => Message::AdvancedBurstData(payload),
+                None => Message::BurstData(payload),
+            }
+        })
+        .collect()
+}
+
+fn notify_waiters(notifiers: &Mutex<Vec<MessageNotifier>>, message: Message) {
+    let mut notifiers = notifiers.lock().unwrap();
+    let (matched, waiting) = notifiers
+        .drain(..)
+        .partition(|notifier| (notifier.matcher)(message.clone()));
+    *notifiers = waiting;
+
+    for notifier in matched {
+        if notifier.sender.send(Ok(message.clone())).is_err() {
+            error!("failed to notify of message: {:?}", message)
+        }
+    }
+}
+
+/// Fail every pending waiter with the given error, as the message it's waiting for may have been
+/// lost.
+fn fail_waiters(notifiers: &Mutex<Vec<MessageNotifier>>, error: Error) {
+    let mut notifiers = notifiers.lock().unwrap();
+    for notifier in notifiers.drain(..) {
+        // The waiter may have already timed out and dropped its receiver
+        let _ = notifier.sender.send(Err(error.clone()));
+    }
+}
+
+/// Reopen the transport after a disconnect, retrying at the given interval until it opens or
+/// the node is closed. Returns whether the transport was reopened.
+fn reconnect(link: &Link, interval: Duration, closed: &AtomicBool) -> bool {
+    loop {
+        thread::sleep(interval);
+
+        if closed.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        match link.transport.open() {
+            Ok(()) => return true,
+            Err(e) => trace!("reconnect failed: {:?}", e),
+        }
+    }
+}
+
+/// Restore the node after reconnecting: set the network key again, then reassign and reopen
+/// every channel that hasn't closed with its original configuration.
+fn restore(
+    link: &Link,
+    network_key: [u8; 8],
+    assigned: &RwLock<HashMap<u8, Mutex<ChannelAssignment>>>,
+) -> Result<(), Error> {
+    link.initialize(network_key)?;
+
+    let mut channels: Vec<u8> = assigned.read().unwrap().keys().copied().collect();
+    channels.sort();
+
+    for channel in channels {
+        // Take the configuration so the assignment isn't locked while waiting for responses,
+        // as data for the channel may arrive before the last response
+        let configuration = match assigned.read().unwrap().get(&channel) {
+            Some(assignment) => {
+                let mut assignment = assignment.lock().unwrap();
+                if assignment.status == ChannelStatus::Closed {
+                    continue;
+                }
+                // Transfers in progress were lost with the node's reset
+                if std::mem::take(&mut assignment.response_pending) {
+                    if let Some(processor) = assignment.processor() {
+                        processor.process_event(MessageCode::EventTransferTXFailed);
+                    }
+                }
+                assignment.configuration.take()
+            }
+            None => continue,
+        };
+
+        let result = match &configuration {
+            Some(ChannelConfiguration::Device(device, options)) => {
+                link.configure_device(channel, device.as_ref(), *options)
+            }
+            Some(ChannelConfiguration::Search(options)) => link.configure_search(channel, *options),
+            Some(ChannelConfiguration::Transmitter(configuration)) => {
+                link.configure_transmitter(channel, *configuration)
+            }
+            None => Ok(()),
+        };
+
+        if let Some(assignment) = assigned.read().unwrap().get(&channel) {
+            let mut assignment = assignment.lock().unwrap();
+            assignment.configuration = configuration;
+        }
+
+        result?;
+    }
+
+    Ok(())
+}
+
+/// Messaging with the node over its transport, shared between the node and the threads
+/// receiving messages and reconnecting.
+#[derive(Clone)]
+struct Link {
+    transport: Arc<dyn Transport>,
+    notifiers: Arc<Mutex<Vec<MessageNotifier>>>,
+    /// Channels with acknowledged data being sent by `send_acknowledged`
+    sending: Arc<Mutex<HashSet<u8>>>,
+}
+
+impl Link {
+    /// Reset the node and set the network key.
+    fn initialize(&self, network_key: [u8; 8]) -> Result<(), Error> {
+        // Nodes signal they're ready after reset with a startup message, but fall back to
+        // waiting for the full timeout for any that don't
+        match self.wait_for_message_after(
+            Box::new(|message| matches!(message, Message::StartupMessage(_))),
+            Duration::from_millis(2000),
+            || self.write_message(Message::ResetSystem, Duration::from_millis(100)),
+        ) {
+            Ok(_) | Err(Error::Timeout) => {}
+            Err(e) => return Err(e),
+        }
+
+        let set_network_key = Message::SetNetworkKey(message::SetNetworkKeyData {
+            network: 0,
+            key: network_key,
+        });
+        self.expect_channel_response_no_error_after(
+            0,
+            MessageID::SetNetworkKey,
+            Duration::from_millis(1000),
+            || self.write_message(set_network_key, Duration::from_millis(100)),
+        )
+    }
+
+    /// Configure and open a channel for a background scanning search.
+    fn configure_search(&self, channel: u8, options: Option<ChannelOptions>) -> Result<(), Error> {
+        let enable_extended_messages =
+            Message::EnableExtendedMessages(message::EnableExtendedMessagesData { enabled: 1 });
+        // Extended messages are enabled for the whole node, so the response is always on
+        // channel 0 rather than the channel being assigned
+        self.expect_channel_response_no_error_after(
+            0,
+            MessageID::EnableExtendedMessages,
+            Duration::from_millis(100),
+            || self.write_message(enable_extended_messages, Duration::from_millis(100)),
+        )?;
+
+        let assign_channel = Message::AssignChannel(message::AssignChannelData {
+            channel,
+            channel_type: message::ChannelType::Receive,
+            network: 0,
+            extended_assignment: message::ChannelExtendedAssignment::BACKGROUND_SCANNING,
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::AssignChannel,
+            Duration::from_millis(100),
+            || self.write_message(assign_channel, Duration::from_millis(100)),
+        )?;
+
+        let set_channel_id = Message::SetChannelID(message::SetChannelIDData {
+            channel,
+            device: 0,
+            pairing: false,
+            device_type: 0,
+            transmission_type: 0,
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelID,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_id, Duration::from_secs(100)),
+        )?;
+
+        let set_channel_period = Message::SetChannelPeriod(message::SetChannelPeriodData {
+            channel,
+            period: 8070,
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelPeriod,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_period, Duration::from_millis(100)),
+        )?;
+
+        let set_channel_rf_freq =
+            Message::SetChannelRFFrequency(message::SetChannelRFFrequencyData {
+                channel,
+                frequency: 57,
+            });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelRFFrequency,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_rf_freq, Duration::from_millis(100)),
+        )?;
+
+        self.configure_options(channel, options)?;
+
+        self.open_channel(channel)
+    }
+
+    /// Configure and open a channel for the given device.
+    fn configure_device(
+        &self,
+        channel: u8,
+        device: &dyn device::Device,
+        options: Option<ChannelOptions>,
+    ) -> Result<(), Error> {
+        let assign_channel = Message::AssignChannel(message::AssignChannelData {
+            channel,
+            channel_type: device.channel_type(),
+            network: 0,
+            extended_assignment: message::ChannelExtendedAssignment::empty(),
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::AssignChannel,
+            Duration::from_millis(100),
+            || self.write_message(assign_channel, Duration::from_millis(100)),
+        )?;
+
+        let pairing = device.pairing();
+        let set_channel_id = Message::SetChannelID(message::SetChannelIDData {
+            channel,
+            device: pairing.device_id,
+            pairing: false,
+            device_type: device.device_type(),
+            transmission_type: pairing.transmission_type,
+        });
+
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelID,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_id, Duration::from_secs(100)),
+        )?;
+
+        let set_channel_period = Message::SetChannelPeriod(message::SetChannelPeriodData {
+            channel,
+            period: device.channel_period(),
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelPeriod,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_period, Duration::from_millis(100)),
+        )?;
+
+        let set_channel_rf_freq =
+            Message::SetChannelRFFrequency(message::SetChannelRFFrequencyData {
+                channel,
+                frequency: device.rf_frequency(),
+            });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelRFFrequency,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_rf_freq, Duration::from_millis(100)),
+        )?;
+
+        self.configure_options(channel, options)?;
+
+        self.open_channel(channel)
+    }
+
+    /// Configure and open a channel to transmit as a device.
+    fn configure_transmitter(
+        &self,
+        channel: u8,
+        configuration: TransmitChannel,
+    ) -> Result<(), Error> {
+        let assign_channel = Message::AssignChannel(message::AssignChannelData {
+            channel,
+            channel_type: configuration.channel_type,
+            network: 0,
+            extended_assignment: message::ChannelExtendedAssignment::empty(),
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::AssignChannel,
+            Duration::from_millis(100),
+            || self.write_message(assign_channel, Duration::from_millis(100)),
+        )?;
+
+        let set_channel_id = Message::SetChannelID(message::SetChannelIDData {
+            channel,
+            device: configuration.channel_id.device_number,
+            pairing: false,
+            device_type: configuration.channel_id.device_type,
+            transmission_type: configuration.channel_id.transmission_type,
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelID,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_id, Duration::from_millis(100)),
+        )?;
+
+        let set_channel_period = Message::SetChannelPeriod(message::SetChannelPeriodData {
+            channel,
+            period: configuration.channel_period,
+        });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelPeriod,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_period, Duration::from_millis(100)),
+        )?;
+
+        let set_channel_rf_freq =
+            Message::SetChannelRFFrequency(message::SetChannelRFFrequencyData {
+                channel,
+                frequency: configuration.rf_frequency,
+            });
+        self.expect_channel_response_no_error_after(
+            channel,
+            MessageID::SetChannelRFFrequency,
+            Duration::from_millis(100),
+            || self.write_message(set_channel_rf_freq, Duration::from_millis(100)),
+        )?;
+
+        self.open_channel(channel)
+    }
+
+    fn configure_options(&self, channel: u8, options: Option<ChannelOptions>) -> Result<(), Error> {
+        if let Some(options) = options {
+            if let Some(timeout) = options.low_priority_search_timeout {
+                let search_timeout = Message::SetChannelLowPrioritySearchTimeout(
+                    message::SetChannelLowPrioritySearchTimeoutData { channel, timeout },
+                );
+                self.expect_channel_response_no_error_after(
+                    channel,
+                    MessageID::SetChannelLowPrioritySearchTimeout,
+                    Duration::from_millis(100),
+                    || self.write_message(search_timeout, Duration::from_millis(100)),
+                )?;
+            }
+
+            if let Some(timeout) = options.search_timeout {
+                let search_timeout =
                     Message::SetChannelSearchTimeout(message::SetChannelSearchTimeoutData {
                         channel,
                         timeout,
@@ -475,24 +1302,80 @@ impl Node {
             }
         }
 
+        Ok(())
+    }
+
+    fn open_channel(&self, channel: u8) -> Result<(), Error> {
         let open_channel = Message::OpenChannel(message::OpenChannelData { channel });
         self.expect_channel_response_no_error_after(
             channel,
             MessageID::OpenChannel,
             Duration::from_millis(100),
             || self.write_message(open_channel, Duration::from_millis(100)),
-        )?;
+        )
+    }
 
-        {
-            let assigned = self.assigned.read().unwrap();
-            let assignment = assigned
-                .get(&channel)
-                .expect("should contain new assignment");
-            let mut assignment = assignment.lock().unwrap();
-            assignment.status = ChannelStatus::Open;
-        }
+    fn send_acknowledged(&self, channel: u8, payload: [u8; 8], retries: u8) -> Result<(), Error> {
+        // Responses aren't sent while sending, so the transfer events are for this data
+        self.sending.lock().unwrap().insert(channel);
+        let result = self.send_acknowledged_retrying(channel, payload, retries);
+        self.sending.lock().unwrap().remove(&channel);
+        result
+    }
 
-        Ok(channel)
+    fn send_acknowledged_retrying(
+        &self,
+        channel: u8,
+        payload: [u8; 8],
+        retries: u8,
+    ) -> Result<(), Error> {
+        let mut attempt = 0;
+        loop {
+            let message = Message::AcknowledgedData(message::DataPayload {
+                channel,
+                data: Some(payload),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            });
+            // Acknowledgements arrive in the channel period after sending, so allow for slow
+            // channel periods
+            let response = self.wait_for_message_after(
+                Box::new(move |message| match message {
+                    Message::ChannelResponseEvent(data) if data.channel == channel => {
+                        match data.message_id {
+                            MessageID::ChannelEvent => matches!(
+                                data.message_code,
+                                MessageCode::EventTransferTXCompleted
+                                    | MessageCode::EventTransferTXFailed
+                            ),
+                            MessageID::AcknowledgedData => true,
+                            _ => false,
+                        }
+                    }
+                    _ => false,
+                }),
+                Duration::from_secs(2),
+                || self.write_message(message, Duration::from_millis(100)),
+            )?;
+
+            let Message::ChannelResponseEvent(data) = response else {
+                unreachable!()
+            };
+            match data.message_code {
+                MessageCode::EventTransferTXCompleted => return Ok(()),
+                MessageCode::EventTransferTXFailed if attempt < retries => {
+                    attempt += 1;
+                    trace!(
+                        "acknowledged data failed on channel {}, retry {} of {}",
+                        channel,
+                        attempt,
+                        retries
+                    );
+                }
+                code => return Err(Error::TransferFailed(code)),
+            }
+        }
     }
 
     fn expect_channel_response_no_error_after<T, F: FnOnce() -> Result<T, Error>>(
@@ -525,7 +1408,7 @@ impl Node {
         }
     }
 
-    pub fn wait_for_message_after<T, F: FnOnce() -> Result<T, Error>>(
+    fn wait_for_message_after<T, F: FnOnce() -> Result<T, Error>>(
         &self,
         matcher: Box<dyn Fn(Message) -> bool + Send>,
         timeout: Duration,
@@ -533,239 +1416,835 @@ impl Node {
     ) -> Result<Message, Error> {
         let receiver = self.notify(matcher);
         (after)()?;
-        Ok(receiver.recv_timeout(timeout)?)
+        receiver.recv_timeout(timeout)?
     }
 
     fn notify(
         &self,
         matcher: Box<dyn Fn(Message) -> bool + Send>,
-    ) -> crossbeam_channel::Receiver<Message> {
-        static ID_SEQ: AtomicU64 = AtomicU64::new(0);
+    ) -> crossbeam_channel::Receiver<Result<Message, Error>> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        self.add_notifier(matcher, NotifierSender::Blocking(sender));
+        receiver
+    }
 
-        let id = ID_SEQ.fetch_add(1, Ordering::Relaxed);
+    #[cfg(feature = "async")]
+    fn notify_async(
+        &self,
+        matcher: Box<dyn Fn(Message) -> bool + Send>,
+    ) -> tokio::sync::oneshot::Receiver<Result<Message, Error>> {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        self.add_notifier(matcher, NotifierSender::Async(sender));
+        receiver
+    }
 
-        let (sender, receiver) = crossbeam_channel::bounded(1);
+    fn add_notifier(&self, matcher: Box<dyn Fn(Message) -> bool + Send>, sender: NotifierSender) {
         let mut notifiers = self.notifiers.lock().unwrap();
-        notifiers.push(MessageNotifier {
-            id,
-            matcher,
-            sender: sender.clone(),
-        });
-        receiver
+        notifiers.push(MessageNotifier { matcher, sender });
     }
 
-    fn receive_messages(&self) -> Result<(), Error> {
-        let (tx, rx) = crossbeam_channel::unbounded();
-        let endpoint = self.in_ep.ok_or(Error::EndpointNotInitialized)?;
-        let handle = Arc::clone(&self.handle);
+    fn write_message(&self, message: message::Message, timeout: Duration) -> Result<(), Error> {
+        self.transport.write(message.encode().as_ref(), timeout)?;
 
-        thread::spawn(move || {
-            let reader = HandleReader { endpoint, handle };
-            let publisher = reader::Publisher::new(&reader, tx, 4096);
-            publisher.run().expect("publisher run failed");
+        trace!("sent: {}", message);
+        Ok(())
+    }
+}
+
+pub trait Reader {
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, crate::node::Error>;
+}
+
+/// Connection to an ANT node, e.g. a USB stick.
+///
+/// Implementations must be safe to share between threads: `Node` reads from the transport on a
+/// background thread while writing commands from the caller's thread.
+pub trait Transport: Reader + Send + Sync {
+    /// Open the underlying device so it's ready to read and write messages. May be called again
+    /// to reopen the device after it has been disconnected.
+    fn open(&self) -> Result<(), Error>;
+    /// Reset the underlying device, e.g. when closing the node.
+    fn reset(&self) -> Result<(), Error>;
+    fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error>;
+}
+
+struct TransportReader {
+    transport: Arc<dyn Transport>,
+}
+
+impl Reader for TransportReader {
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, crate::node::Error> {
+        self.transport.read(buf, timeout)
+    }
+}
+
+/// Number of times acknowledged data is retried by default, see
+/// [`NodeBuilder::acknowledged_retries`].
+pub const DEFAULT_ACKNOWLEDGED_RETRIES: u8 = 3;
+
+pub struct NodeBuilder {
+    stick: usb::StickSelector,
+    network_key: [u8; 8],
+    transport: Option<Arc<dyn Transport>>,
+    reconnect_interval: Option<Duration>,
+    acknowledged_retries: u8,
+    device_number: Option<u16>,
+}
+
+impl NodeBuilder {
+    pub fn new(network_key: [u8; 8]) -> NodeBuilder {
+        NodeBuilder {
+            stick: usb::StickSelector::First,
+            network_key,
+            transport: None,
+            reconnect_interval: None,
+            acknowledged_retries: DEFAULT_ACKNOWLEDGED_RETRIES,
+            device_number: None,
+        }
+    }
+
+    /// Open the USB stick at the given bus number and address, see `usb::list_sticks`.
+    pub fn usb_bus_address(mut self, bus_number: u8, address: u8) -> NodeBuilder {
+        self.stick = usb::StickSelector::BusAddress {
+            bus_number,
+            address,
+        };
+        self
+    }
+
+    /// Open the USB stick with the given serial number, see `usb::list_sticks`.
+    pub fn usb_serial_number(mut self, serial_number: &str) -> NodeBuilder {
+        self.stick = usb::StickSelector::SerialNumber(serial_number.to_string());
+        self
+    }
+
+    /// Use the given transport instead of the default USB stick transport.
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> NodeBuilder {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Try to reopen the transport at the given interval after it's disconnected, restoring the
+    /// network key and channels once reopened. See [`Event`] for the events reported.
+    pub fn auto_reconnect(mut self, interval: Duration) -> NodeBuilder {
+        self.reconnect_interval = Some(interval);
+        self
+    }
+
+    /// Number of times [`Node::send_acknowledged`] retries sending data the device didn't
+    /// acknowledge.
+    pub fn acknowledged_retries(mut self, retries: u8) -> NodeBuilder {
+        self.acknowledged_retries = retries;
+        self
+    }
+
+    /// Device number used in the channel ID of channels transmitting as a device, see
+    /// [`Node::assign_transmitter`].
+    pub fn device_number(mut self, device_number: u16) -> NodeBuilder {
+        self.device_number = Some(device_number);
+        self
+    }
+
+    pub fn build(&self) -> Node {
+        let transport = match &self.transport {
+            Some(transport) => Arc::clone(transport),
+            None => Arc::new(usb::UsbTransport::new(self.stick.clone())),
+        };
+
+        Node {
+            capabilities: None,
+            network_key: self.network_key,
+            link: Link {
+                transport,
+                notifiers: Arc::new(Mutex::new(vec![])),
+                sending: Arc::new(Mutex::new(HashSet::new())),
+            },
+            assigned: Arc::new(RwLock::new(HashMap::new())),
+            events: {
+                let (sender, receiver) = crossbeam_channel::bounded(EVENTS_CAPACITY);
+                let sender = EventSender {
+                    sender,
+                    #[cfg(feature = "async")]
+                    streams: Arc::new(Mutex::new(vec![])),
+                };
+                (sender, receiver)
+            },
+            reconnect_interval: self.reconnect_interval,
+            closed: Arc::new(AtomicBool::new(false)),
+            dropped_frames: Arc::new(AtomicU64::new(0)),
+            advanced_burst: None,
+            acknowledged_retries: self.acknowledged_retries,
+            device_number: self.device_number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::DevicePairing;
+    use crate::message::ChannelID;
+    use crate::profile::{fitness_equipment, heart_rate_monitor, weight_scale};
+
+    const KEY: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    const HRM: ChannelID = ChannelID {
+        device_number: 47330,
+        device_type: 120,
+        transmission_type: 1,
+    };
+
+    fn open_node(emulator: &emulator::Emulator) -> Node {
+        let mut node = NodeBuilder::new(KEY).transport(emulator.clone()).build();
+        node.open().expect("node should open");
+        node
+    }
+
+    #[test]
+    fn it_opens_node() {
+        let emulator = emulator::Emulator::new();
+        let node = open_node(&emulator);
+
+        assert_eq!(emulator.network_key(0), Some(KEY));
+        let capabilities = node.capabilities().expect("capabilities should be set");
+        assert_eq!(capabilities.max_channels, 8);
+        assert!(capabilities.extended_message_enabled);
+    }
+
+    #[test]
+    fn it_assigns_channel_and_processes_data() {
+        let emulator = emulator::Emulator::new();
+        emulator.add_sensor(HRM, vec![[4, 27, 222, 94, 173, 98, 26, 63]]);
+        let mut node = open_node(&emulator);
+
+        let (hrm, receiver) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: HRM.device_number,
+            transmission_type: HRM.transmission_type,
         });
+        let channel = node.assign_channel(Box::new(hrm), None).unwrap();
+        assert!(emulator.channel_open(channel));
+        assert_eq!(
+            node.channel_status(channel),
+            Some((ChannelStatus::Open, vec![]))
+        );
+
+        emulator.tick();
+        let data = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(data.computed_heart_rate, 63);
+        assert_eq!(data.heartbeat_event_time, 25261);
+    }
 
-        let assigned = Arc::clone(&self.assigned);
-        let notifiers = Arc::clone(&self.notifiers);
+    #[test]
+    fn it_only_receives_data_from_paired_device() {
+        let emulator = emulator::Emulator::new();
+        emulator.add_sensor(
+            ChannelID {
+                device_number: 12345,
+                device_type: 17,
+                transmission_type: 5,
+            },
+            vec![[16, 25, 72, 150, 13, 20, 255, 36]],
+        );
+        let mut node = open_node(&emulator);
+
+        let (trainer, receiver) = fitness_equipment::new_paired(DevicePairing {
+            device_id: 54321,
+            transmission_type: 5,
+        });
+        node.assign_channel(Box::new(trainer), None).unwrap();
 
-        thread::spawn(move || {
-            let send_notifications = move |message| {
-                let mut notifiers = notifiers.lock().unwrap();
-                let mut to_delete = vec![];
-                for notifier in notifiers.iter() {
-                    if (notifier.matcher)(message) {
-                        to_delete.push(notifier.id);
-                        if let Err(e) = notifier.sender.try_send(message) {
-                            error!("failed to notify of message: {:?}: {}", message, e)
-                        }
-                    }
-                }
+        emulator.tick();
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+    }
 
-                notifiers.retain(|n| !to_delete.contains(&n.id));
-            };
+    #[test]
+    fn it_searches_for_devices() {
+        let emulator = emulator::Emulator::new();
+        let trainer = ChannelID {
+            device_number: 12345,
+            device_type: 17,
+            transmission_type: 5,
+        };
+        emulator.add_sensor(HRM, vec![[4, 27, 222, 94, 173, 98, 26, 63]]);
+        emulator.add_sensor(trainer, vec![[16, 25, 72, 150, 13, 20, 255, 36]]);
+        let mut node = open_node(&emulator);
+
+        let (_, receiver) = node.search(None).unwrap();
+
+        emulator.tick();
+        emulator.tick();
+        let found: Vec<ChannelID> = (0..2)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(1)).unwrap())
+            .collect();
+        assert_eq!(found, vec![HRM, trainer]);
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+    }
 
-            loop {
-                match rx.recv() {
-                    Ok(message) => {
-                        trace!("received: {}", message);
-
-                        match message {
-                            Message::BroadcastData(data) | Message::AcknowledgedData(data) => {
-                                let assigned = assigned.read().unwrap();
-                                if let Some(assignment) = assigned.get(&data.channel) {
-                                    let mut assignment = assignment.lock().unwrap();
-                                    if let Some(ref mut device) = assignment.device {
-                                        if let Err(e) = device.process_data(data) {
-                                            error!("Error processing data: {:?}", e);
-                                        }
-                                    }
-                                }
-                            }
-                            Message::ChannelResponseEvent(data) => {
-                                if data.message_id == MessageID::ChannelEvent {
-                                    let assigned = assigned.read().unwrap();
-                                    if let Some(assignment) = assigned.get(&data.channel) {
-                                        let mut assignment = assignment.lock().unwrap();
-                                        if data.message_code == MessageCode::EventChannelClosed {
-                                            assignment.status = ChannelStatus::Closed;
-                                            assignment.device = None;
-                                        }
-                                        assignment.events.push(data.message_code);
-                                    }
-                                }
-                                send_notifications(message);
-                            }
-                            _ => {
-                                send_notifications(message);
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        error!("error receiving from publisher");
-                        break;
-                    }
-                }
-            }
+    #[test]
+    fn it_closes_and_frees_channel() {
+        let emulator = emulator::Emulator::new();
+        let mut node = open_node(&emulator);
+
+        let (hrm, receiver) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: HRM.device_number,
+            transmission_type: HRM.transmission_type,
         });
+        let channel = node.assign_channel(Box::new(hrm), None).unwrap();
+
+        assert_eq!(node.free_channel(channel), Err(Error::ChannelInvalidState));
+
+        node.close_channel(channel).unwrap();
+        assert!(!emulator.channel_open(channel));
+        assert_eq!(
+            node.channel_status(channel),
+            Some((ChannelStatus::Closed, vec![MessageCode::EventChannelClosed]))
+        );
+        // Processor is dropped when the channel closes
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(100)),
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected)
+        );
+
+        assert_eq!(node.free_channel(channel), Ok(()));
+        assert_eq!(node.channel_status(channel), None);
+    }
 
-        Ok(())
+    struct BurstProcessor {
+        sender: crossbeam_channel::Sender<(u8, Vec<u8>)>,
     }
 
-    pub fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
-        let handle = self.handle.read().unwrap();
-        let endpoint = self.in_ep.ok_or(Error::EndpointNotInitialized)?;
-        match handle
-            .as_ref()
-            .expect("no handle")
-            .read_bulk(endpoint.address, buf, timeout)
-        {
-            Ok(size) => Ok(size),
-            Err(rusb::Error::Timeout) => Err(Error::Timeout),
-            Err(e) => Err(e.into()),
+    impl device::DataProcessor for BurstProcessor {
+        fn process_data(&mut self, _data: message::DataPayload) -> Result<(), device::Error> {
+            Ok(())
+        }
+
+        fn process_burst(&mut self, channel: u8, data: Vec<u8>) -> Result<(), device::Error> {
+            self.sender.try_send((channel, data))?;
+            Ok(())
         }
     }
 
-    pub fn write_message(&self, message: message::Message, timeout: Duration) -> Result<(), Error> {
-        self.write(message.encode().as_ref(), timeout)?;
+    fn sent_bursts(emulator: &emulator::Emulator) -> Vec<message::BurstDataPayload> {
+        emulator
+            .received()
+            .into_iter()
+            .filter_map(|message| match message {
+                Message::BurstData(data) | Message::AdvancedBurstData(data) => Some(data),
+                _ => None,
+            })
+            .collect()
+    }
 
-        trace!("sent: {}", message);
-        Ok(())
+    #[test]
+    fn it_sends_bursts() {
+        let emulator = emulator::Emulator::new();
+        let mut node = open_node(&emulator);
+
+        let (hrm, _) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: HRM.device_number,
+            transmission_type: HRM.transmission_type,
+        });
+        let channel = node.assign_channel(Box::new(hrm), None).unwrap();
+
+        let data: Vec<u8> = (1..=20).collect();
+        assert_eq!(node.send_burst(channel, &data), Ok(()));
+
+        let packets = sent_bursts(&emulator);
+        assert_eq!(
+            packets
+                .iter()
+                .map(|p| (p.sequence, p.last))
+                .collect::<Vec<_>>(),
+            vec![(0, false), (1, false), (2, true)]
+        );
+        assert_eq!(packets[2].data, vec![17, 18, 19, 20, 0, 0, 0, 0]);
+
+        assert_eq!(
+            node.send_burst(channel + 1, &data),
+            Err(Error::TransferFailed(MessageCode::ChannelNotOpened))
+        );
     }
 
-    pub fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
-        let handle = self.handle.read().unwrap();
-        let endpoint = self.out_ep.ok_or(Error::EndpointNotInitialized)?;
-        match handle
-            .as_ref()
-            .expect("no handle")
-            .write_bulk(endpoint.address, buf, timeout)
-        {
-            Ok(size) => Ok(size),
-            Err(rusb::Error::Timeout) => Err(Error::Timeout),
-            Err(e) => Err(e.into()),
+    #[test]
+    fn it_sends_advanced_bursts() {
+        let emulator = emulator::Emulator::new();
+        let mut node = open_node(&emulator);
+
+        let (hrm, _) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: HRM.device_number,
+            transmission_type: HRM.transmission_type,
+        });
+        let channel = node.assign_channel(Box::new(hrm), None).unwrap();
+
+        node.configure_advanced_burst(message::ConfigureAdvancedBurstData {
+            enabled: true,
+            max_packet_length: message::AdvancedBurstPacketLength::Bytes16,
+            required_features: 0,
+            optional_features: 0,
+        })
+        .unwrap();
+
+        let data: Vec<u8> = (1..=20).collect();
+        assert_eq!(node.send_burst(channel, &data), Ok(()));
+
+        let packets = sent_bursts(&emulator);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].data, (1..=16).collect::<Vec<u8>>());
+        assert_eq!(packets[1].data, vec![17, 18, 19, 20, 0, 0, 0, 0]);
+        assert!(packets[1].last);
+    }
+
+    #[test]
+    fn it_reassembles_received_bursts() {
+        let emulator = emulator::Emulator::new();
+        let mut node = open_node(&emulator);
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let channel = node
+            ._assign_channel(Some(Box::new(BurstProcessor { sender })))
+            .unwrap();
+
+        let data: Vec<u8> = (1..=40).collect();
+        emulator.send_burst(channel, &data);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(1)),
+            Ok((channel, data))
+        );
+
+        // Transfers missing a packet are abandoned
+        emulator.send_raw(
+            Message::BurstData(message::BurstDataPayload {
+                channel,
+                sequence: 2,
+                last: true,
+                data: vec![0; 8],
+            })
+            .encode(),
+        );
+        emulator.send_burst(channel, &[1, 2, 3]);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(1)),
+            Ok((channel, vec![1, 2, 3, 0, 0, 0, 0, 0]))
+        );
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn it_sends_acknowledged_data_with_retries() {
+        let emulator = emulator::Emulator::new();
+        let mut node = NodeBuilder::new(KEY)
+            .transport(emulator.clone())
+            .acknowledged_retries(2)
+            .build();
+        node.open().unwrap();
+
+        let (trainer, _) = fitness_equipment::new_paired(DevicePairing {
+            device_id: 12345,
+            transmission_type: 5,
+        });
+        let channel = node.assign_channel(Box::new(trainer), None).unwrap();
+        let payload = [49, 0xff, 0xff, 0xff, 0xff, 0xff, 0x20, 0x03];
+
+        let sent = |emulator: &emulator::Emulator| {
+            emulator
+                .received()
+                .iter()
+                .filter(|m| matches!(m, Message::AcknowledgedData(_)))
+                .count()
+        };
+
+        emulator.fail_acknowledged(2);
+        assert_eq!(node.send_acknowledged(channel, payload), Ok(()));
+        assert_eq!(sent(&emulator), 3);
+
+        emulator.fail_acknowledged(3);
+        assert_eq!(
+            node.send_acknowledged(channel, payload),
+            Err(Error::TransferFailed(MessageCode::EventTransferTXFailed))
+        );
+        assert_eq!(sent(&emulator), 6);
+
+        assert_eq!(
+            node.send_acknowledged(channel + 1, payload),
+            Err(Error::TransferFailed(MessageCode::ChannelNotOpened))
+        );
+    }
+
+    struct TestTransmitter {
+        heartbeat_count: u8,
+        sender: crossbeam_channel::Sender<message::DataPayload>,
+    }
+
+    impl device::DataProcessor for TestTransmitter {
+        fn process_data(&mut self, data: message::DataPayload) -> Result<(), device::Error> {
+            self.sender.try_send(data)?;
+            Ok(())
+        }
+
+        /// Bursts are passed on as a payload of their first 8 bytes
+        fn process_burst(&mut self, channel: u8, data: Vec<u8>) -> Result<(), device::Error> {
+            self.sender.try_send(message::DataPayload {
+                channel,
+                data: data[..8].try_into().ok(),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            })?;
+            Ok(())
         }
     }
 
-    fn find_device(&self) -> Result<rusb::Device<rusb::GlobalContext>, Error> {
-        let devices = rusb::devices()?;
+    impl device::Transmitter for TestTransmitter {
+        fn device_type(&self) -> u8 {
+            120
+        }
 
-        for device in devices.iter() {
-            let descriptor = device.device_descriptor()?;
+        fn transmission_type(&self) -> u8 {
+            1
+        }
 
-            if descriptor.vendor_id() == self.vendor_id
-                && descriptor.product_id() == self.product_id
-            {
-                return Ok(device);
-            }
+        fn rf_frequency(&self) -> u8 {
+            57
+        }
+
+        fn channel_period(&self) -> u16 {
+            8070
         }
 
-        Err(Error::DeviceNotFound)
+        fn next_page(&mut self) -> [u8; 8] {
+            self.heartbeat_count += 1;
+            [0, 0xff, 0xff, 0xff, 0, 0, self.heartbeat_count, 80]
+        }
     }
 
-    fn find_endpoints(&self) -> Result<(Endpoint, Endpoint), Error> {
-        let device = self.device.clone().ok_or(Error::DeviceNotInitialized)?;
+    #[test]
+    fn it_transmits_as_device() {
+        let (sender, commands) = crossbeam_channel::unbounded();
+
+        let mut node = open_node(&emulator::Emulator::new());
+        assert!(matches!(
+            node.assign_transmitter(Box::new(TestTransmitter {
+                heartbeat_count: 0,
+                sender: sender.clone(),
+            })),
+            Err(Error::DeviceNumberNotSet)
+        ));
+
+        let emulator = emulator::Emulator::new();
+        let mut node = NodeBuilder::new(KEY)
+            .transport(emulator.clone())
+            .device_number(4321)
+            .build();
+        node.open().unwrap();
+
+        let channel = node
+            .assign_transmitter(Box::new(TestTransmitter {
+                heartbeat_count: 0,
+                sender,
+            }))
+            .unwrap();
+        assert!(emulator.channel_open(channel));
+        assert!(emulator
+            .received()
+            .contains(&Message::SetChannelID(message::SetChannelIDData {
+                channel,
+                device: 4321,
+                pairing: false,
+                device_type: 120,
+                transmission_type: 1,
+            })));
+
+        let (hrm, receiver) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: 4321,
+            transmission_type: 1,
+        });
+        let hrm_channel = node.assign_channel(Box::new(hrm), None).unwrap();
+
+        for expected in 1..=2 {
+            emulator.tick();
+            let data = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+            assert_eq!(data.computed_heart_rate, 80);
+            assert_eq!(data.heartbeat_count, expected);
+        }
 
-        let config = device.config_descriptor(0)?;
+        let command = [70, 0xff, 0xff, 0xff, 0xff, 1, 1, 1];
+        node.send_acknowledged(hrm_channel, command).unwrap();
+        let data = commands.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(data.channel, channel);
+        assert_eq!(data.data, Some(command));
+    }
 
-        let interfaces = config.interfaces();
+    #[test]
+    fn it_sends_device_responses() {
+        const SCALE: ChannelID = ChannelID {
+            device_number: 1234,
+            device_type: 119,
+            transmission_type: 1,
+        };
 
-        let mut in_endpoint = None;
-        let mut out_endpoint = None;
+        let emulator = emulator::Emulator::new();
+        // scale supporting user profile exchange, without a user profile selected
+        emulator.add_sensor(SCALE, vec![[1, 0xff, 0xff, 0x01, 0xff, 0xff, 0xfe, 0xff]]);
+        let mut node = open_node(&emulator);
 
-        for interface in interfaces {
-            for descriptor in interface.descriptors() {
-                for endpoint in descriptor.endpoint_descriptors() {
-                    if endpoint.usage_type() == rusb::UsageType::Data
-                        && endpoint.transfer_type() == rusb::TransferType::Bulk
-                    {
-                        let result = Some(Endpoint {
-                            interface: interface.number(),
-                            address: endpoint.address(),
-                        });
-
-                        match endpoint.direction() {
-                            rusb::Direction::In => in_endpoint = result,
-                            rusb::Direction::Out => out_endpoint = result,
-                        }
+        let (scale, receiver) = weight_scale::new_paired(DevicePairing {
+            device_id: SCALE.device_number,
+            transmission_type: SCALE.transmission_type,
+        });
+        let user_profile = scale.user_profile_updater();
+        let channel = node.assign_channel(Box::new(scale), None).unwrap();
+        user_profile
+            .send(weight_scale::UserProfile {
+                id: 16,
+                gender: weight_scale::Gender::Male,
+                age: 40,
+                height: 180,
+                activity_level: 3,
+                athlete: false,
+            })
+            .unwrap();
+
+        // The user profile isn't sent while other data is being sent to the scale, and the
+        // transfer events of that data aren't taken for the user profile's
+        emulator.send_raw_before_response(
+            Message::BroadcastData(message::DataPayload {
+                channel,
+                data: Some([1, 0xff, 0xff, 0x01, 0xff, 0xff, 0xfe, 0xff]),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            })
+            .encode(),
+        );
+        node.send_acknowledged(channel, [0xff; 8]).unwrap();
+        receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        let wait_for_event = |code: MessageCode| {
+            for _ in 0..100 {
+                if let Some((_, events)) = node.channel_status(channel) {
+                    if events.contains(&code) {
+                        return;
                     }
                 }
+                thread::sleep(Duration::from_millis(10));
             }
-        }
+            panic!("{:?} was not reported", code);
+        };
 
-        if let Some(in_ep) = in_endpoint {
-            if let Some(out_ep) = out_endpoint {
-                return Ok((in_ep, out_ep));
-            }
+        // The first transfer fails, so the user profile is sent again for the next page
+        emulator.fail_acknowledged(1);
+        emulator.tick();
+        wait_for_event(MessageCode::EventTransferTXFailed);
+        emulator.tick();
+        wait_for_event(MessageCode::EventTransferTXCompleted);
+
+        // Once sent, the user profile isn't sent again while the scale reports the same request
+        for _ in 0..3 {
+            emulator.tick();
+            receiver.recv_timeout(Duration::from_secs(1)).unwrap();
         }
 
-        Err(Error::EndpointNotFound)
+        let response = Message::AcknowledgedData(message::DataPayload {
+            channel,
+            data: Some([58, 16, 0, 0x80, 0xff, 0x80 | 40, 180, 3]),
+            channel_id: None,
+            rssi: None,
+            rx_timestamp: None,
+        });
+        let sent = emulator
+            .received()
+            .iter()
+            .filter(|message| **message == response)
+            .count();
+        assert_eq!(sent, 2);
     }
-}
 
-pub trait Reader {
-    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, crate::node::Error>;
-}
+    #[test]
+    fn it_reports_disconnect() {
+        let emulator = emulator::Emulator::new();
+        let node = open_node(&emulator);
 
-pub struct HandleReader {
-    handle: Arc<RwLock<Option<rusb::DeviceHandle<rusb::GlobalContext>>>>,
-    endpoint: Endpoint,
-}
+        emulator.unplug();
+        assert_eq!(
+            node.events().recv_timeout(Duration::from_secs(1)),
+            Ok(Event::Disconnected(Error::USBError(rusb::Error::NoDevice)))
+        );
+    }
 
-impl Reader for HandleReader {
-    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, crate::node::Error> {
-        let guard = self.handle.read().unwrap();
-        let handle = guard.as_ref().ok_or(Error::HandleNotInitialized)?;
-        Ok(handle.read_bulk(self.endpoint.address, buf, timeout)?)
+    #[test]
+    fn it_reconnects_and_restores_channels() {
+        let emulator = emulator::Emulator::new();
+        emulator.add_sensor(HRM, vec![[4, 27, 222, 94, 173, 98, 26, 63]]);
+        let mut node = NodeBuilder::new(KEY)
+            .transport(emulator.clone())
+            .auto_reconnect(Duration::from_millis(10))
+            .build();
+        node.open().unwrap();
+
+        let (hrm, receiver) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: HRM.device_number,
+            transmission_type: HRM.transmission_type,
+        });
+        let channel = node.assign_channel(Box::new(hrm), None).unwrap();
+        let (search_channel, _) = node.search(None).unwrap();
+
+        emulator.unplug();
+        let events = node.events();
+        assert!(matches!(
+            events.recv_timeout(Duration::from_secs(1)),
+            Ok(Event::Disconnected(_))
+        ));
+        assert!(!emulator.channel_open(channel));
+
+        emulator.plug_in();
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(2)),
+            Ok(Event::Reconnected)
+        );
+        assert_eq!(emulator.network_key(0), Some(KEY));
+        assert!(emulator.channel_open(channel));
+        assert!(emulator.channel_open(search_channel));
+
+        emulator.tick();
+        let data = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(data.computed_heart_rate, 63);
     }
-}
 
-pub struct NodeBuilder {
-    vendor_id: u16,
-    product_id: u16,
-    network_key: [u8; 8],
-}
+    #[test]
+    fn it_fails_waiters_on_disconnect() {
+        let emulator = emulator::Emulator::new();
+        let node = open_node(&emulator);
 
-impl NodeBuilder {
-    pub fn new(network_key: [u8; 8]) -> NodeBuilder {
-        NodeBuilder {
-            vendor_id: DYNASTREAM_INNOVATIONS_VID,
-            product_id: DI_ANT_M_STICK,
-            network_key,
-        }
+        let start = std::time::Instant::now();
+        let result = node.wait_for_message_after(
+            Box::new(|message| matches!(message, Message::Capabilities(_))),
+            Duration::from_secs(5),
+            || {
+                emulator.unplug();
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(Error::USBError(rusb::Error::NoDevice)));
+        assert!(start.elapsed() < Duration::from_secs(1));
     }
 
-    pub fn build(&self) -> Node {
-        Node {
-            capabilities: None,
-            vendor_id: self.vendor_id,
-            product_id: self.product_id,
-            network_key: self.network_key,
-            device: None,
-            handle: Arc::new(RwLock::new(None)),
-            in_ep: None,
-            out_ep: None,
-            notifiers: Arc::new(Mutex::new(vec![])),
-            assigned: Arc::new(RwLock::new(HashMap::new())),
+    #[test]
+    fn it_reports_decode_errors_and_continues() {
+        let emulator = emulator::Emulator::new();
+        let node = open_node(&emulator);
+
+        let mut corrupted =
+            Message::StartupMessage(message::StartupMessageData { reason: 0x20 }).encode();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+
+        emulator.send_raw(corrupted);
+        assert_eq!(
+            node.events().recv_timeout(Duration::from_secs(1)),
+            Ok(Event::DecodeError(message::Error::InvalidChecksum))
+        );
+        assert_eq!(node.dropped_frames(), 1);
+
+        let request = Message::RequestMessage(RequestMessageData {
+            channel: 0,
+            message_id: MessageID::Capabilities,
+        });
+        let result = node.wait_for_message_after(
+            Box::new(|message| matches!(message, Message::Capabilities(_))),
+            Duration::from_secs(1),
+            || node.write_message(request, Duration::from_millis(100)),
+        );
+        assert!(matches!(result, Ok(Message::Capabilities(_))));
+    }
+
+    #[test]
+    fn it_ignores_decode_errors_while_waiting() {
+        let emulator = emulator::Emulator::new();
+        let mut node = open_node(&emulator);
+
+        let mut corrupted =
+            Message::StartupMessage(message::StartupMessageData { reason: 0x20 }).encode();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        emulator.send_raw_before_response(corrupted);
+
+        let (hrm, _) = heart_rate_monitor::new_search();
+        let channel = node.assign_channel(Box::new(hrm), None).unwrap();
+        assert!(emulator.channel_open(channel));
+        assert_eq!(
+            node.events().recv_timeout(Duration::from_secs(1)),
+            Ok(Event::DecodeError(message::Error::InvalidChecksum))
+        );
+        assert_eq!(node.dropped_frames(), 1);
+    }
+
+    #[test]
+    fn it_drops_events_when_not_received() {
+        let emulator = emulator::Emulator::new();
+        let node = open_node(&emulator);
+
+        let startup =
+            Message::StartupMessage(message::StartupMessageData { reason: 0x20 }).encode();
+        let mut corrupted = startup.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        let frames = 2 * EVENTS_CAPACITY as u64;
+        for _ in 0..frames {
+            // Valid frames in between, so each corrupt frame is dropped separately
+            emulator.send_raw(corrupted.clone());
+            emulator.send_raw(startup.clone());
+        }
+
+        let start = std::time::Instant::now();
+        while node.dropped_frames() < frames && start.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(10));
         }
+        assert_eq!(node.dropped_frames(), frames);
+        assert_eq!(node.events().try_iter().count(), EVENTS_CAPACITY);
+    }
+
+    #[test]
+    fn it_frees_channels_which_fail_to_configure() {
+        let emulator = emulator::Emulator::new();
+        let mut node = NodeBuilder::new(KEY)
+            .transport(emulator.clone())
+            .device_number(4321)
+            .build();
+        node.open().unwrap();
+
+        emulator.unplug();
+        let (hrm, _) = heart_rate_monitor::new_search();
+        assert!(node.assign_channel(Box::new(hrm), None).is_err());
+        let (sender, _) = crossbeam_channel::unbounded();
+        assert!(node
+            .assign_transmitter(Box::new(TestTransmitter {
+                heartbeat_count: 0,
+                sender,
+            }))
+            .is_err());
+        assert!(node.search(None).is_err());
+
+        assert_eq!(node.available_channels(), Ok(8));
+    }
+
+    #[test]
+    fn it_passes_bursts_to_transmitters() {
+        let emulator = emulator::Emulator::new();
+        let mut node = NodeBuilder::new(KEY)
+            .transport(emulator.clone())
+            .device_number(4321)
+            .build();
+        node.open().unwrap();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let channel = node
+            .assign_transmitter(Box::new(TestTransmitter {
+                heartbeat_count: 0,
+                sender,
+            }))
+            .unwrap();
+
+        let data: Vec<u8> = (1..=16).collect();
+        emulator.send_burst(channel, &data);
+        let payload = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(payload.channel, channel);
+        assert_eq!(payload.data, Some([1, 2, 3, 4, 5, 6, 7, 8]));
     }
 }
@@ -0,0 +1,395 @@
+//! Async interface to `Node` for tokio applications, enabled with the `async` feature.
+//!
+//! Node operations block while waiting for responses, so they're run on tokio's blocking thread
+//! pool. Waiting for messages, device data and events don't block a thread: they're fed to tokio
+//! channels by the node's processing thread.
+
+use core::future::Future;
+use core::time::Duration;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
+
+use super::{ChannelOptions, ChannelStatus, Error, Event, EventSender, Link, Node};
+use crate::device;
+use crate::message::{self, Message, MessageCode};
+
+/// Forwards the items a processor sends to its crossbeam channel to a stream, from the node's
+/// processing thread once the processor has run.
+struct Forwarder<T> {
+    receiver: crossbeam_channel::Receiver<T>,
+    sender: mpsc::UnboundedSender<T>,
+}
+
+impl<T> Forwarder<T> {
+    fn new(receiver: crossbeam_channel::Receiver<T>) -> (Forwarder<T>, UnboundedReceiverStream<T>) {
+        let (sender, stream_receiver) = mpsc::unbounded_channel();
+        let forwarder = Forwarder { receiver, sender };
+        (forwarder, UnboundedReceiverStream::new(stream_receiver))
+    }
+
+    fn forward(&self) {
+        for item in self.receiver.try_iter() {
+            // The stream may have been dropped, in which case items are discarded
+            let _ = self.sender.send(item);
+        }
+    }
+}
+
+impl<T> Clone for Forwarder<T> {
+    fn clone(&self) -> Self {
+        Forwarder {
+            receiver: self.receiver.clone(),
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+struct StreamProcessor<T> {
+    processor: Box<dyn device::DataProcessor + Send>,
+    forwarder: Forwarder<T>,
+}
+
+impl<T> device::DataProcessor for StreamProcessor<T> {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), device::Error> {
+        let result = self.processor.process_data(data);
+        self.forwarder.forward();
+        result
+    }
+
+    fn process_burst(&mut self, channel: u8, data: Vec<u8>) -> Result<(), device::Error> {
+        let result = self.processor.process_burst(channel, data);
+        self.forwarder.forward();
+        result
+    }
+
+    fn response(&mut self) -> Option<[u8; 8]> {
+        self.processor.response()
+    }
+
+    fn process_event(&mut self, code: MessageCode) {
+        self.processor.process_event(code)
+    }
+}
+
+/// Device whose data processors forward its data to a stream, see
+/// [`AsyncNode::assign_channel_stream`].
+struct StreamDevice<T> {
+    device: Box<dyn device::Device + Send>,
+    forwarder: Forwarder<T>,
+}
+
+impl<T> device::DataProcessor for StreamDevice<T> {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), device::Error> {
+        let result = self.device.process_data(data);
+        self.forwarder.forward();
+        result
+    }
+
+    fn process_burst(&mut self, channel: u8, data: Vec<u8>) -> Result<(), device::Error> {
+        let result = self.device.process_burst(channel, data);
+        self.forwarder.forward();
+        result
+    }
+
+    fn response(&mut self) -> Option<[u8; 8]> {
+        self.device.response()
+    }
+
+    fn process_event(&mut self, code: MessageCode) {
+        self.device.process_event(code)
+    }
+}
+
+impl<T: Send + 'static> device::Device for StreamDevice<T> {
+    fn channel_type(&self) -> message::ChannelType {
+        self.device.channel_type()
+    }
+
+    fn device_type(&self) -> u8 {
+        self.device.device_type()
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        self.device.rf_frequency()
+    }
+
+    fn channel_period(&self) -> u16 {
+        self.device.channel_period()
+    }
+
+    fn pairing(&self) -> device::DevicePairing {
+        self.device.pairing()
+    }
+
+    fn as_data_processor(&self) -> Box<dyn device::DataProcessor + Send> {
+        Box::new(StreamProcessor {
+            processor: self.device.as_data_processor(),
+            forwarder: self.forwarder.clone(),
+        })
+    }
+}
+
+/// Async wrapper around a `Node`.
+pub struct AsyncNode {
+    node: Arc<Mutex<Node>>,
+    link: Link,
+    events: EventSender,
+    acknowledged_retries: u8,
+}
+
+impl AsyncNode {
+    pub fn new(node: Node) -> AsyncNode {
+        let link = node.link.clone();
+        let events = node.events.0.clone();
+        let acknowledged_retries = node.acknowledged_retries;
+        AsyncNode {
+            node: Arc::new(Mutex::new(node)),
+            link,
+            events,
+            acknowledged_retries,
+        }
+    }
+
+    pub async fn open(&self) -> Result<(), Error> {
+        self.with_node(|node| node.open()).await
+    }
+
+    pub async fn close(&self) -> Result<(), Error> {
+        self.with_node(|node| node.close()).await
+    }
+
+    /// Stream of events such as the node being disconnected, see [`Node::events`].
+    ///
+    /// Unlike the node's receivers, each stream receives every event reported after it's created.
+    pub fn events(&self) -> ReceiverStream<Event> {
+        ReceiverStream::new(self.events.stream())
+    }
+
+    pub async fn assign_channel(
+        &self,
+        device: Box<dyn device::Device + Send>,
+        options: Option<ChannelOptions>,
+    ) -> Result<u8, Error> {
+        self.with_node(move |node| node.assign_channel(device, options))
+            .await
+    }
+
+    /// Assign a channel to the device, streaming the data it sends to `receiver`, e.g. as returned
+    /// with the device by a profile's `new_paired`.
+    ///
+    /// The stream ends once the channel is closed.
+    pub async fn assign_channel_stream<T: Send + 'static>(
+        &self,
+        device: Box<dyn device::Device + Send>,
+        receiver: crossbeam_channel::Receiver<T>,
+        options: Option<ChannelOptions>,
+    ) -> Result<(u8, UnboundedReceiverStream<T>), Error> {
+        let (forwarder, stream) = Forwarder::new(receiver);
+        let device = Box::new(StreamDevice { device, forwarder });
+        let channel = self.assign_channel(device, options).await?;
+        Ok((channel, stream))
+    }
+
+    pub async fn assign_transmitter(
+        &self,
+        transmitter: Box<dyn device::Transmitter + Send>,
+    ) -> Result<u8, Error> {
+        self.with_node(move |node| node.assign_transmitter(transmitter))
+            .await
+    }
+
+    pub async fn search(
+        &self,
+        options: Option<ChannelOptions>,
+    ) -> Result<(u8, UnboundedReceiverStream<message::ChannelID>), Error> {
+        let (search, receiver) = device::Search::new();
+        let (forwarder, stream) = Forwarder::new(receiver);
+        let search = Box::new(StreamProcessor {
+            processor: Box::new(search),
+            forwarder,
+        });
+        let channel = self
+            .with_node(move |node| node.assign_search(search, options))
+            .await?;
+        Ok((channel, stream))
+    }
+
+    pub async fn close_channel(&self, channel: u8) -> Result<(), Error> {
+        self.with_node(move |node| node.close_channel(channel))
+            .await
+    }
+
+    pub async fn free_channel(&self, channel: u8) -> Result<(), Error> {
+        self.with_node(move |node| node.free_channel(channel)).await
+    }
+
+    pub async fn channel_status(&self, channel: u8) -> Option<(ChannelStatus, Vec<MessageCode>)> {
+        self.with_node(move |node| Ok(node.channel_status(channel)))
+            .await
+            .unwrap_or(None)
+    }
+
+    pub async fn write_message(&self, message: Message, timeout: Duration) -> Result<(), Error> {
+        let link = self.link.clone();
+        run_blocking(move || link.write_message(message, timeout)).await
+    }
+
+    /// Send acknowledged data to a device, see [`Node::send_acknowledged`].
+    pub async fn send_acknowledged(&self, channel: u8, payload: [u8; 8]) -> Result<(), Error> {
+        let link = self.link.clone();
+        let retries = self.acknowledged_retries;
+        run_blocking(move || link.send_acknowledged(channel, payload, retries)).await
+    }
+
+    /// Wait for a message matching `matcher`, which must be received within `timeout` after
+    /// `after` completes, e.g. waiting for the response to a message written by `after`.
+    pub async fn wait_for_message_after<T, F: Future<Output = Result<T, Error>>>(
+        &self,
+        matcher: Box<dyn Fn(Message) -> bool + Send>,
+        timeout: Duration,
+        after: F,
+    ) -> Result<Message, Error> {
+        let receiver = self.link.notify_async(matcher);
+        after.await?;
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(Error::ChannelDisconnected),
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    async fn with_node<
+        T: Send + 'static,
+        F: FnOnce(&mut Node) -> Result<T, Error> + Send + 'static,
+    >(
+        &self,
+        f: F,
+    ) -> Result<T, Error> {
+        let node = Arc::clone(&self.node);
+        run_blocking(move || f(&mut node.lock().unwrap())).await
+    }
+}
+
+async fn run_blocking<T: Send + 'static, F: FnOnce() -> Result<T, Error> + Send + 'static>(
+    f: F,
+) -> Result<T, Error> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::device::DevicePairing;
+    use crate::message::{ChannelID, MessageID, RequestMessageData};
+    use crate::node::{emulator::Emulator, NodeBuilder};
+    use crate::profile::heart_rate_monitor;
+
+    const KEY: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    const HRM: ChannelID = ChannelID {
+        device_number: 47330,
+        device_type: 120,
+        transmission_type: 1,
+    };
+
+    async fn open_node(emulator: &Emulator) -> AsyncNode {
+        let node = AsyncNode::new(NodeBuilder::new(KEY).transport(emulator.clone()).build());
+        node.open().await.expect("node should open");
+        node
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_streams_device_data() {
+        let emulator = Emulator::new();
+        emulator.add_sensor(HRM, vec![[4, 27, 222, 94, 173, 98, 26, 63]]);
+        let node = open_node(&emulator).await;
+
+        let (hrm, receiver) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: HRM.device_number,
+            transmission_type: HRM.transmission_type,
+        });
+        let (channel, mut stream) = node
+            .assign_channel_stream(Box::new(hrm), receiver, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            node.channel_status(channel).await,
+            Some((ChannelStatus::Open, vec![]))
+        );
+
+        emulator.tick();
+        let data = stream.next().await.unwrap();
+        assert_eq!(data.computed_heart_rate, 63);
+
+        node.close_channel(channel).await.unwrap();
+        assert!(!emulator.channel_open(channel));
+        // The stream ends once the channel is closed and its processor dropped
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_streams_search_results() {
+        let emulator = Emulator::new();
+        emulator.add_sensor(HRM, vec![[4, 27, 222, 94, 173, 98, 26, 63]]);
+        let node = open_node(&emulator).await;
+
+        let (_, mut stream) = node.search(None).await.unwrap();
+
+        emulator.tick();
+        assert_eq!(stream.next().await, Some(HRM));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_waits_for_message() {
+        let emulator = Emulator::new();
+        let node = open_node(&emulator).await;
+
+        let request = Message::RequestMessage(RequestMessageData {
+            channel: 0,
+            message_id: MessageID::Capabilities,
+        });
+        let message = node
+            .wait_for_message_after(
+                Box::new(|message| matches!(message, Message::Capabilities(_))),
+                Duration::from_secs(1),
+                node.write_message(request, Duration::from_millis(100)),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(message, Message::Capabilities(data) if data.max_channels == 8));
+
+        assert_eq!(
+            node.wait_for_message_after(
+                Box::new(|message| matches!(message, Message::StartupMessage(_))),
+                Duration::from_millis(100),
+                async { Ok(()) },
+            )
+            .await,
+            Err(Error::Timeout)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_streams_events() {
+        let emulator = Emulator::new();
+        let node = open_node(&emulator).await;
+
+        let mut first = node.events();
+        let mut second = node.events();
+        let mut corrupted =
+            Message::StartupMessage(message::StartupMessageData { reason: 0x20 }).encode();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        emulator.send_raw(corrupted);
+
+        let expected = Event::DecodeError(message::Error::InvalidChecksum);
+        assert_eq!(first.next().await, Some(expected.clone()));
+        assert_eq!(second.next().await, Some(expected));
+    }
+}
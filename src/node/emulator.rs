@@ -0,0 +1,703 @@
+use core::time::Duration;
+use std::collections::{hash_map, HashMap};
+use std::sync::{Arc, Mutex};
+
+use super::{Error, Reader, Transport};
+use crate::message::{
+    self, BurstDataPayload, CapabilitiesAdvancedOptions, CapabilitiesAdvancedOptions2,
+    CapabilitiesAdvancedOptions3, CapabilitiesAdvancedOptions4, CapabilitiesData,
+    CapabilitiesStandardOptions, ChannelExtendedAssignment, ChannelID, ChannelResponseEventData,
+    ChannelType, DataPayload, Message, MessageCode, MessageID,
+};
+
+/// Startup message reason reported after a ResetSystem command.
+const RESET_COMMAND: u8 = 0x20;
+
+#[derive(Clone, Copy, Debug)]
+struct ChannelState {
+    channel_type: ChannelType,
+    extended_assignment: ChannelExtendedAssignment,
+    channel_id: ChannelID,
+    open: bool,
+}
+
+struct VirtualSensor {
+    channel_id: ChannelID,
+    pages: Vec<[u8; 8]>,
+    next_page: usize,
+}
+
+struct State {
+    connected: bool,
+    capabilities: CapabilitiesData,
+    extended_messages: bool,
+    network_keys: HashMap<u8, [u8; 8]>,
+    channels: HashMap<u8, ChannelState>,
+    sensors: Vec<VirtualSensor>,
+    received: Vec<Message>,
+    failed_acknowledgements: usize,
+    raw_before_response: Option<Vec<u8>>,
+}
+
+impl State {
+    fn reset(&mut self) {
+        self.channels.clear();
+        self.network_keys.clear();
+        self.extended_messages = false;
+    }
+}
+
+/// In-process emulation of an ANT node, for exercising `Node` without hardware.
+///
+/// The emulator responds to commands written by the node with the same channel responses a
+/// USB stick would, and broadcasts scripted data pages from virtual sensors each time
+/// [`Emulator::tick`] is called. Clones share the same emulated node, so a clone can be kept
+/// to drive the emulator after passing it to [`super::NodeBuilder::transport`].
+#[derive(Clone)]
+pub struct Emulator {
+    state: Arc<Mutex<State>>,
+    sender: crossbeam_channel::Sender<Vec<u8>>,
+    receiver: crossbeam_channel::Receiver<Vec<u8>>,
+    pending: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emulator {
+    /// Create an emulator reporting the capabilities of an ANT-M stick.
+    pub fn new() -> Emulator {
+        Emulator::with_max_channels(8)
+    }
+
+    /// Create an emulator reporting the capabilities of an ANT-M stick, but with the given
+    /// number of channels.
+    pub fn with_max_channels(max_channels: u8) -> Emulator {
+        Emulator::with_capabilities(CapabilitiesData {
+            max_channels,
+            max_networks: 8,
+            standard_options: CapabilitiesStandardOptions::empty(),
+            advanced_options: CapabilitiesAdvancedOptions::NETWORK_ENABLED
+                | CapabilitiesAdvancedOptions::SERIAL_NUMBER_ENABLED
+                | CapabilitiesAdvancedOptions::PER_CHANNEL_TX_POWER_ENABLED
+                | CapabilitiesAdvancedOptions::LOW_PRIORITY_SEARCH_ENABLED
+                | CapabilitiesAdvancedOptions::SEARCH_LIST_ENABLED,
+            advanced_options_2: CapabilitiesAdvancedOptions2::EXT_MESSAGE_ENABLED
+                | CapabilitiesAdvancedOptions2::SCAN_MODE_ENABLED
+                | CapabilitiesAdvancedOptions2::PROX_SEARCH_ENABLED
+                | CapabilitiesAdvancedOptions2::EXT_ASSIGN_ENABLED,
+            max_sensrcore_channels: 0,
+            advanced_options_3: CapabilitiesAdvancedOptions3::all(),
+            advanced_options_4: CapabilitiesAdvancedOptions4::empty(),
+        })
+    }
+
+    pub fn with_capabilities(capabilities: CapabilitiesData) -> Emulator {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Emulator {
+            state: Arc::new(Mutex::new(State {
+                connected: true,
+                capabilities,
+                extended_messages: false,
+                network_keys: HashMap::new(),
+                channels: HashMap::new(),
+                sensors: vec![],
+                received: vec![],
+                failed_acknowledgements: 0,
+                raw_before_response: None,
+            })),
+            sender,
+            receiver,
+            pending: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Add a virtual sensor which broadcasts the given pages in turn, repeating from the first
+    /// page once all have been sent.
+    pub fn add_sensor(&self, channel_id: ChannelID, pages: Vec<[u8; 8]>) {
+        let mut state = self.state.lock().unwrap();
+        state.sensors.push(VirtualSensor {
+            channel_id,
+            pages,
+            next_page: 0,
+        });
+    }
+
+    /// Emulate unplugging the node: reads and writes fail with `rusb::Error::NoDevice`, and the
+    /// node loses its configuration and any messages not yet read.
+    pub fn unplug(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.connected = false;
+        state.reset();
+        self.pending.lock().unwrap().clear();
+        while self.receiver.try_recv().is_ok() {}
+    }
+
+    /// Emulate plugging the node back in after [`Emulator::unplug`], so it can be opened again.
+    pub fn plug_in(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.connected = true;
+    }
+
+    /// Queue raw bytes to be read by the node, e.g. to emulate corrupted messages.
+    pub fn send_raw(&self, data: Vec<u8>) {
+        self.sender
+            .send(data)
+            .expect("emulator receiver should exist");
+    }
+
+    /// Queue raw bytes to be read by the node just before the response to the next message it
+    /// writes, e.g. to emulate a corrupted message arriving while the node waits for a response.
+    pub fn send_raw_before_response(&self, data: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        state.raw_before_response = Some(data);
+    }
+
+    /// Send data to the node as a burst transfer on the given channel, padding the final packet
+    /// with zeros.
+    pub fn send_burst(&self, channel: u8, data: &[u8]) {
+        let chunks: Vec<&[u8]> = if data.is_empty() {
+            vec![&[]]
+        } else {
+            data.chunks(8).collect()
+        };
+        let count = chunks.len();
+
+        let mut sequence = 0;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let mut packet = chunk.to_vec();
+            packet.resize(8, 0);
+            self.send(Message::BurstData(BurstDataPayload {
+                channel,
+                sequence,
+                last: i == count - 1,
+                data: packet,
+            }));
+            sequence = if sequence == 3 { 1 } else { sequence + 1 };
+        }
+    }
+
+    /// Fail the next `count` acknowledged data transfers from the node with
+    /// `EventTransferTXFailed`, as if the device didn't acknowledge them.
+    pub fn fail_acknowledged(&self, count: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.failed_acknowledgements = count;
+    }
+
+    /// Network key set for the given network, if any.
+    pub fn network_key(&self, network: u8) -> Option<[u8; 8]> {
+        let state = self.state.lock().unwrap();
+        state.network_keys.get(&network).copied()
+    }
+
+    /// Whether the given channel is currently open.
+    pub fn channel_open(&self, channel: u8) -> bool {
+        let state = self.state.lock().unwrap();
+        state.channels.get(&channel).is_some_and(|c| c.open)
+    }
+
+    /// All messages written to the emulator since it was created, in the order received.
+    pub fn received(&self) -> Vec<Message> {
+        let state = self.state.lock().unwrap();
+        state.received.clone()
+    }
+
+    /// Broadcast the next page from each virtual sensor to the open receive channels matching
+    /// its channel ID, then report `EventTX` on each open transmit channel.
+    ///
+    /// Channels with a wildcard channel ID that are assigned for background scanning receive
+    /// pages from every matching sensor, while other channels receive pages from the first
+    /// matching sensor only, as if paired with it. Pages the node broadcasts on its transmit
+    /// channels are relayed to its receive channels matching the transmitter's channel ID.
+    pub fn tick(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        let pages: Vec<(ChannelID, [u8; 8])> = state
+            .sensors
+            .iter_mut()
+            .filter(|sensor| !sensor.pages.is_empty())
+            .map(|sensor| {
+                let page = sensor.pages[sensor.next_page];
+                sensor.next_page = (sensor.next_page + 1) % sensor.pages.len();
+                (sensor.channel_id, page)
+            })
+            .collect();
+
+        let mut channels: Vec<(u8, ChannelState)> =
+            state.channels.iter().map(|(&n, &c)| (n, c)).collect();
+        channels.sort_by_key(|(n, _)| *n);
+
+        for &(channel, channel_state) in &channels {
+            if !channel_state.open || !is_receive(channel_state.channel_type) {
+                continue;
+            }
+
+            let matching = pages
+                .iter()
+                .filter(|(id, _)| matches_channel_id(channel_state.channel_id, *id));
+            let scanning = channel_state
+                .extended_assignment
+                .contains(ChannelExtendedAssignment::BACKGROUND_SCANNING);
+
+            for (id, page) in matching.take(if scanning { usize::MAX } else { 1 }) {
+                self.send(Message::BroadcastData(DataPayload {
+                    channel,
+                    data: Some(*page),
+                    channel_id: if state.extended_messages {
+                        Some(*id)
+                    } else {
+                        None
+                    },
+                    rssi: None,
+                    rx_timestamp: None,
+                }));
+            }
+        }
+
+        for (channel, channel_state) in channels {
+            if channel_state.open && !is_receive(channel_state.channel_type) {
+                self.respond(channel, MessageID::ChannelEvent, MessageCode::EventTX);
+            }
+        }
+    }
+
+    fn check_connected(&self) -> Result<(), Error> {
+        let state = self.state.lock().unwrap();
+        if state.connected {
+            Ok(())
+        } else {
+            Err(Error::USBError(rusb::Error::NoDevice))
+        }
+    }
+
+    fn send(&self, message: Message) {
+        // The receiver is owned by the emulator, so this can't fail while self exists
+        self.sender
+            .send(message.encode())
+            .expect("emulator receiver should exist");
+    }
+
+    fn respond(&self, channel: u8, message_id: MessageID, message_code: MessageCode) {
+        self.send(Message::ChannelResponseEvent(ChannelResponseEventData {
+            channel,
+            message_id,
+            message_code,
+        }));
+    }
+
+    /// Relay data written by the host on one channel to the node's other open channels it would
+    /// reach over the air: data from transmit channels to receive channels matching the
+    /// transmitter's channel ID, and data from receive channels to the transmit channels they
+    /// match.
+    fn relay(&self, state: &State, data: &DataPayload, acknowledged: bool) {
+        let Some(source) = state.channels.get(&data.channel).filter(|c| c.open) else {
+            return;
+        };
+
+        let mut targets: Vec<(u8, ChannelState)> = state
+            .channels
+            .iter()
+            .filter(|(&n, c)| n != data.channel && c.open)
+            .filter(|(_, c)| {
+                if is_receive(source.channel_type) {
+                    !is_receive(c.channel_type)
+                        && matches_channel_id(source.channel_id, c.channel_id)
+                } else {
+                    is_receive(c.channel_type)
+                        && matches_channel_id(c.channel_id, source.channel_id)
+                }
+            })
+            .map(|(&n, &c)| (n, c))
+            .collect();
+        targets.sort_by_key(|(n, _)| *n);
+
+        for (channel, _) in targets {
+            let payload = DataPayload {
+                channel,
+                data: data.data,
+                channel_id: if state.extended_messages {
+                    Some(source.channel_id)
+                } else {
+                    None
+                },
+                rssi: None,
+                rx_timestamp: None,
+            };
+            self.send(if acknowledged {
+                Message::AcknowledgedData(payload)
+            } else {
+                Message::BroadcastData(payload)
+            });
+        }
+    }
+
+    fn process(&self, message: Message) {
+        let mut state = self.state.lock().unwrap();
+        state.received.push(message.clone());
+        if let Some(data) = state.raw_before_response.take() {
+            self.send_raw(data);
+        }
+
+        match message {
+            Message::ResetSystem => {
+                state.reset();
+                self.send(Message::StartupMessage(message::StartupMessageData {
+                    reason: RESET_COMMAND,
+                }));
+            }
+            Message::SetNetworkKey(data) => {
+                if data.network < state.capabilities.max_networks {
+                    state.network_keys.insert(data.network, data.key);
+                    self.respond(
+                        data.network,
+                        MessageID::SetNetworkKey,
+                        MessageCode::ResponseNoError,
+                    );
+                } else {
+                    self.respond(
+                        data.network,
+                        MessageID::SetNetworkKey,
+                        MessageCode::InvalidNetworkNumber,
+                    );
+                }
+            }
+            Message::RequestMessage(data) => match data.message_id {
+                MessageID::Capabilities => self.send(Message::Capabilities(state.capabilities)),
+                _ => self.respond(
+                    data.channel,
+                    MessageID::RequestMessage,
+                    MessageCode::InvalidMessage,
+                ),
+            },
+            Message::EnableExtendedMessages(data) => {
+                state.extended_messages = data.enabled != 0;
+                self.respond(
+                    0,
+                    MessageID::EnableExtendedMessages,
+                    MessageCode::ResponseNoError,
+                );
+            }
+            Message::LibConfig(_) => {
+                self.respond(0, MessageID::LibConfig, MessageCode::ResponseNoError);
+            }
+            Message::AssignChannel(data) => {
+                let code = if data.channel >= state.capabilities.max_channels {
+                    MessageCode::InvalidParameterProvided
+                } else if data.network >= state.capabilities.max_networks {
+                    MessageCode::InvalidNetworkNumber
+                } else if let hash_map::Entry::Vacant(e) = state.channels.entry(data.channel) {
+                    e.insert(ChannelState {
+                        channel_type: data.channel_type,
+                        extended_assignment: data.extended_assignment,
+                        channel_id: ChannelID {
+                            device_number: 0,
+                            device_type: 0,
+                            transmission_type: 0,
+                        },
+                        open: false,
+                    });
+                    MessageCode::ResponseNoError
+                } else {
+                    MessageCode::ChannelInWrongState
+                };
+                self.respond(data.channel, MessageID::AssignChannel, code);
+            }
+            Message::SetChannelID(data) => {
+                let code = match state.channels.get_mut(&data.channel) {
+                    Some(channel) => {
+                        channel.channel_id = ChannelID {
+                            device_number: data.device,
+                            device_type: data.device_type,
+                            transmission_type: data.transmission_type,
+                        };
+                        MessageCode::ResponseNoError
+                    }
+                    None => MessageCode::ChannelInWrongState,
+                };
+                self.respond(data.channel, MessageID::SetChannelID, code);
+            }
+            Message::SetChannelPeriod(data) => {
+                let code = assigned_code(&state, data.channel);
+                self.respond(data.channel, MessageID::SetChannelPeriod, code);
+            }
+            Message::SetChannelRFFrequency(data) => {
+                let code = assigned_code(&state, data.channel);
+                self.respond(data.channel, MessageID::SetChannelRFFrequency, code);
+            }
+            Message::SetChannelSearchTimeout(data) => {
+                let code = assigned_code(&state, data.channel);
+                self.respond(data.channel, MessageID::SetChannelSearchTimeout, code);
+            }
+            Message::SetChannelLowPrioritySearchTimeout(data) => {
+                let code = assigned_code(&state, data.channel);
+                self.respond(
+                    data.channel,
+                    MessageID::SetChannelLowPrioritySearchTimeout,
+                    code,
+                );
+            }
+            Message::OpenChannel(data) => {
+                let code = match state.channels.get_mut(&data.channel) {
+                    Some(channel) if !channel.open => {
+                        channel.open = true;
+                        MessageCode::ResponseNoError
+                    }
+                    _ => MessageCode::ChannelInWrongState,
+                };
+                self.respond(data.channel, MessageID::OpenChannel, code);
+            }
+            Message::CloseChannel(data) => match state.channels.get_mut(&data.channel) {
+                Some(channel) if channel.open => {
+                    channel.open = false;
+                    self.respond(
+                        data.channel,
+                        MessageID::CloseChannel,
+                        MessageCode::ResponseNoError,
+                    );
+                    self.respond(
+                        data.channel,
+                        MessageID::ChannelEvent,
+                        MessageCode::EventChannelClosed,
+                    );
+                }
+                _ => self.respond(
+                    data.channel,
+                    MessageID::CloseChannel,
+                    MessageCode::ChannelInWrongState,
+                ),
+            },
+            Message::ConfigureAdvancedBurst(_) => {
+                self.respond(
+                    0,
+                    MessageID::ConfigureAdvancedBurst,
+                    MessageCode::ResponseNoError,
+                );
+            }
+            Message::BurstData(data) | Message::AdvancedBurstData(data) => {
+                if !state.channels.get(&data.channel).is_some_and(|c| c.open) {
+                    self.respond(
+                        data.channel,
+                        MessageID::BurstData,
+                        MessageCode::ChannelNotOpened,
+                    );
+                } else if data.last {
+                    self.respond(
+                        data.channel,
+                        MessageID::ChannelEvent,
+                        MessageCode::EventTransferTXCompleted,
+                    );
+                }
+            }
+            Message::AcknowledgedData(data) => {
+                if !state.channels.get(&data.channel).is_some_and(|c| c.open) {
+                    self.respond(
+                        data.channel,
+                        MessageID::AcknowledgedData,
+                        MessageCode::ChannelNotOpened,
+                    );
+                } else if state.failed_acknowledgements > 0 {
+                    state.failed_acknowledgements -= 1;
+                    self.respond(
+                        data.channel,
+                        MessageID::ChannelEvent,
+                        MessageCode::EventTransferTXFailed,
+                    );
+                } else {
+                    self.relay(&state, &data, true);
+                    self.respond(
+                        data.channel,
+                        MessageID::ChannelEvent,
+                        MessageCode::EventTransferTXCompleted,
+                    );
+                }
+            }
+            Message::BroadcastData(data) => self.relay(&state, &data, false),
+            Message::Capabilities(_)
+            | Message::ChannelResponseEvent(_)
+            | Message::SerialError(_)
+            | Message::StartupMessage(_)
+            | Message::Unknown { .. } => {
+                self.respond(0, MessageID::ChannelEvent, MessageCode::InvalidMessage);
+            }
+        }
+    }
+}
+
+fn is_receive(channel_type: ChannelType) -> bool {
+    matches!(
+        channel_type,
+        ChannelType::Receive | ChannelType::ReceiveOnly | ChannelType::SharedBidirectionalReceive
+    )
+}
+
+/// Whether a sensor's channel ID matches the channel ID set on a channel, where zero values
+/// set on the channel are wildcards.
+fn matches_channel_id(channel: ChannelID, sensor: ChannelID) -> bool {
+    (channel.device_number == 0 || channel.device_number == sensor.device_number)
+        && (channel.device_type == 0 || channel.device_type == sensor.device_type)
+        && (channel.transmission_type == 0 || channel.transmission_type == sensor.transmission_type)
+}
+
+fn assigned_code(state: &State, channel: u8) -> MessageCode {
+    if state.channels.contains_key(&channel) {
+        MessageCode::ResponseNoError
+    } else {
+        MessageCode::ChannelInWrongState
+    }
+}
+
+impl Transport for Emulator {
+    fn open(&self) -> Result<(), Error> {
+        let state = self.state.lock().unwrap();
+        if state.connected {
+            Ok(())
+        } else {
+            Err(Error::DeviceNotFound)
+        }
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.reset();
+        Ok(())
+    }
+
+    fn write(&self, buf: &[u8], _timeout: Duration) -> Result<usize, Error> {
+        self.check_connected()?;
+
+        let mut offset = 0;
+        while offset < buf.len() {
+            match Message::decode(&buf[offset..]) {
+                Ok((message, len)) => {
+                    self.process(message);
+                    offset += len;
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(buf.len())
+    }
+}
+
+impl Reader for Emulator {
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+        self.check_connected()?;
+
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            *pending = self.receiver.recv_timeout(timeout)?;
+        }
+
+        let size = pending.len().min(buf.len());
+        buf[..size].copy_from_slice(&pending[..size]);
+        pending.drain(..size);
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_message(emulator: &Emulator) -> Message {
+        let mut buf = [0u8; 64];
+        let size = emulator
+            .read(&mut buf, Duration::from_millis(100))
+            .expect("emulator should have a message to read");
+        Message::decode(&buf[..size]).unwrap().0
+    }
+
+    fn write_message(emulator: &Emulator, message: Message) {
+        emulator
+            .write(&message.encode(), Duration::from_millis(100))
+            .unwrap();
+    }
+
+    #[test]
+    fn it_sends_startup_message_after_reset() {
+        let emulator = Emulator::new();
+        write_message(&emulator, Message::ResetSystem);
+        assert_eq!(
+            read_message(&emulator),
+            Message::StartupMessage(message::StartupMessageData {
+                reason: RESET_COMMAND
+            })
+        );
+    }
+
+    #[test]
+    fn it_rejects_commands_for_unassigned_channel() {
+        let emulator = Emulator::new();
+        write_message(
+            &emulator,
+            Message::OpenChannel(message::OpenChannelData { channel: 3 }),
+        );
+        assert_eq!(
+            read_message(&emulator),
+            Message::ChannelResponseEvent(ChannelResponseEventData {
+                channel: 3,
+                message_id: MessageID::OpenChannel,
+                message_code: MessageCode::ChannelInWrongState,
+            })
+        );
+    }
+
+    #[test]
+    fn it_broadcasts_sensor_pages_to_open_channel() {
+        let emulator = Emulator::new();
+        emulator.add_sensor(
+            ChannelID {
+                device_number: 1234,
+                device_type: 120,
+                transmission_type: 1,
+            },
+            vec![[4, 0, 0, 0, 0, 0, 0, 60], [4, 0, 0, 0, 0, 0, 0, 61]],
+        );
+
+        write_message(
+            &emulator,
+            Message::AssignChannel(message::AssignChannelData {
+                channel: 0,
+                channel_type: ChannelType::Receive,
+                network: 0,
+                extended_assignment: ChannelExtendedAssignment::empty(),
+            }),
+        );
+        write_message(
+            &emulator,
+            Message::OpenChannel(message::OpenChannelData { channel: 0 }),
+        );
+        read_message(&emulator); // assign response
+        read_message(&emulator); // open response
+
+        for expected in [60, 61, 60] {
+            emulator.tick();
+            match read_message(&emulator) {
+                Message::BroadcastData(data) => {
+                    assert_eq!(data.channel, 0);
+                    assert_eq!(data.data, Some([4, 0, 0, 0, 0, 0, 0, expected]));
+                }
+                message => panic!("unexpected message: {}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn it_partially_reads_messages() {
+        let emulator = Emulator::new();
+        write_message(&emulator, Message::ResetSystem);
+
+        let mut buf = [0u8; 3];
+        assert_eq!(emulator.read(&mut buf, Duration::from_millis(100)), Ok(3));
+        assert_eq!(buf, [message::SYNC, 1, MessageID::StartupMessage.into()]);
+        assert_eq!(emulator.read(&mut buf, Duration::from_millis(100)), Ok(2));
+        assert_eq!(
+            emulator.read(&mut buf, Duration::from_millis(10)),
+            Err(Error::Timeout)
+        );
+    }
+}
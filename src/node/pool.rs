@@ -0,0 +1,421 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use log::warn;
+
+use super::{ChannelOptions, ChannelStatus, Error, Event, Node};
+use crate::device;
+use crate::message::{self, MessageCode};
+
+/// Location of a channel within a `NodePool`: the pool's identifier for the node, and the
+/// channel number on that node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Placement {
+    pub node: usize,
+    pub channel: u8,
+}
+
+/// Result of moving a channel from a removed node to one of the remaining nodes.
+#[derive(Debug, PartialEq)]
+pub struct Reassignment {
+    pub from: Placement,
+    pub to: Result<Placement, Error>,
+}
+
+/// Pool of opened nodes, e.g. several USB sticks, which spreads channels across its nodes to
+/// support more devices than a single node's `max_channels`.
+///
+/// New channels are placed on the node with the most available channels, with ties going to
+/// the node added first.
+///
+/// The pool receives the events of each node added to it. When a node reports
+/// `Event::Disconnected`, it's removed from the pool and its devices are assigned to the remaining
+/// nodes, as with [`NodePool::remove_node`]. The results are reported by
+/// [`NodePool::reassignments`].
+pub struct NodePool {
+    nodes: Arc<Mutex<BTreeMap<usize, PooledNode>>>,
+    next_id: usize,
+    reassignments: (
+        crossbeam_channel::Sender<Reassignment>,
+        crossbeam_channel::Receiver<Reassignment>,
+    ),
+}
+
+struct PooledNode {
+    node: Node,
+    /// Dropped when the node leaves the pool, stopping the thread watching its events
+    _watching: crossbeam_channel::Sender<()>,
+}
+
+impl Default for NodePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodePool {
+    pub fn new() -> NodePool {
+        NodePool {
+            nodes: Arc::new(Mutex::new(BTreeMap::new())),
+            next_id: 0,
+            reassignments: crossbeam_channel::unbounded(),
+        }
+    }
+
+    /// Add an opened node to the pool, returning the identifier used for it in placements.
+    pub fn add_node(&mut self, node: Node) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let events = node.events();
+        let (watching, removed) = crossbeam_channel::bounded(0);
+        let nodes = Arc::downgrade(&self.nodes);
+        let reassignments = self.reassignments.0.clone();
+        thread::spawn(move || loop {
+            crossbeam_channel::select! {
+                recv(events) -> event => match event {
+                    Ok(Event::Disconnected(e)) => {
+                        warn!("node {} disconnected: {:?}", id, e);
+                        let Some(nodes) = nodes.upgrade() else {
+                            break;
+                        };
+                        let mut nodes = nodes.lock().unwrap();
+                        if let Ok(results) = remove_node(&mut nodes, id) {
+                            for reassignment in results {
+                                // The pool holds a receiver, so this only fails once it's dropped
+                                let _ = reassignments.send(reassignment);
+                            }
+                        }
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                },
+                recv(removed) -> _ => break,
+            }
+        });
+
+        self.lock().insert(
+            id,
+            PooledNode {
+                node,
+                _watching: watching,
+            },
+        );
+        id
+    }
+
+    /// Call `f` with the node, if it's in the pool.
+    pub fn with_node<T>(&self, id: usize, f: impl FnOnce(&Node) -> T) -> Option<T> {
+        self.lock().get(&id).map(|pooled| f(&pooled.node))
+    }
+
+    pub fn node_ids(&self) -> Vec<usize> {
+        self.lock().keys().copied().collect()
+    }
+
+    /// Receiver for the results of moving channels from nodes removed from the pool after they
+    /// were disconnected.
+    pub fn reassignments(&self) -> crossbeam_channel::Receiver<Reassignment> {
+        self.reassignments.1.clone()
+    }
+
+    pub fn assign_channel(
+        &mut self,
+        device: Box<dyn device::Device + Send>,
+        options: Option<ChannelOptions>,
+    ) -> Result<Placement, Error> {
+        assign_channel(&mut self.lock(), device, options)
+    }
+
+    pub fn search(
+        &mut self,
+        options: Option<ChannelOptions>,
+    ) -> Result<(Placement, crossbeam_channel::Receiver<message::ChannelID>), Error> {
+        let mut nodes = self.lock();
+        let (id, node) = least_loaded(&mut nodes)?;
+        let (channel, receiver) = node.search(options)?;
+        Ok((Placement { node: id, channel }, receiver))
+    }
+
+    pub fn close_channel(&mut self, placement: Placement) -> Result<(), Error> {
+        let mut nodes = self.lock();
+        let pooled = nodes.get_mut(&placement.node).ok_or(Error::NodeNotFound)?;
+        pooled.node.close_channel(placement.channel)
+    }
+
+    pub fn free_channel(&mut self, placement: Placement) -> Result<(), Error> {
+        let mut nodes = self.lock();
+        let pooled = nodes.get_mut(&placement.node).ok_or(Error::NodeNotFound)?;
+        pooled.node.free_channel(placement.channel)
+    }
+
+    pub fn channel_status(
+        &self,
+        placement: Placement,
+    ) -> Option<(ChannelStatus, Vec<MessageCode>)> {
+        self.lock()
+            .get(&placement.node)
+            .and_then(|pooled| pooled.node.channel_status(placement.channel))
+    }
+
+    /// Remove a node from the pool, e.g. after its stick has been unplugged, and assign the
+    /// devices from its open channels to the remaining nodes.
+    ///
+    /// The removed node's channels are not closed, as its device is assumed to be gone. Search
+    /// channels are not reassigned.
+    pub fn remove_node(&mut self, id: usize) -> Result<Vec<Reassignment>, Error> {
+        remove_node(&mut self.lock(), id)
+    }
+
+    /// Close every node in the pool.
+    pub fn close(&mut self) -> Result<(), Error> {
+        for pooled in self.lock().values_mut() {
+            pooled.node.close()?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<usize, PooledNode>> {
+        self.nodes.lock().unwrap()
+    }
+}
+
+fn assign_channel(
+    nodes: &mut BTreeMap<usize, PooledNode>,
+    device: Box<dyn device::Device + Send>,
+    options: Option<ChannelOptions>,
+) -> Result<Placement, Error> {
+    let (id, node) = least_loaded(nodes)?;
+    let channel = node.assign_channel(device, options)?;
+    Ok(Placement { node: id, channel })
+}
+
+fn remove_node(
+    nodes: &mut BTreeMap<usize, PooledNode>,
+    id: usize,
+) -> Result<Vec<Reassignment>, Error> {
+    let mut pooled = nodes.remove(&id).ok_or(Error::NodeNotFound)?;
+
+    let mut reassignments = vec![];
+    for assigned in pooled.node.take_assigned_devices() {
+        let from = Placement {
+            node: id,
+            channel: assigned.channel,
+        };
+        let to = assign_channel(nodes, assigned.device, assigned.options);
+        reassignments.push(Reassignment { from, to });
+    }
+
+    Ok(reassignments)
+}
+
+/// Node with the most available channels, skipping nodes which can't report them, e.g. because
+/// they haven't been opened.
+fn least_loaded(nodes: &mut BTreeMap<usize, PooledNode>) -> Result<(usize, &mut Node), Error> {
+    let mut best: Option<(usize, &mut Node, u8)> = None;
+    for (&id, pooled) in nodes.iter_mut() {
+        let Ok(available) = pooled.node.available_channels() else {
+            continue;
+        };
+        if available > 0 && best.as_ref().is_none_or(|(_, _, most)| available > *most) {
+            best = Some((id, &mut pooled.node, available));
+        }
+    }
+
+    best.map(|(id, node, _)| (id, node))
+        .ok_or(Error::NoAvailableChannel)
+}
+
+#[cfg(test)]
+mod test {
+    use core::time::Duration;
+
+    use super::*;
+    use crate::device::DevicePairing;
+    use crate::message::{ChannelID, Message};
+    use crate::node::{emulator::Emulator, NodeBuilder};
+    use crate::profile::heart_rate_monitor;
+
+    const KEY: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn open_node(emulator: &Emulator) -> Node {
+        let mut node = NodeBuilder::new(KEY).transport(emulator.clone()).build();
+        node.open().expect("node should open");
+        node
+    }
+
+    fn hrm(device_id: u16) -> Box<dyn device::Device + Send> {
+        let (hrm, _) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id,
+            transmission_type: 1,
+        });
+        Box::new(hrm)
+    }
+
+    #[test]
+    fn it_places_channels_on_least_loaded_node() {
+        let mut pool = NodePool::new();
+        let a = pool.add_node(open_node(&Emulator::with_max_channels(2)));
+        let b = pool.add_node(open_node(&Emulator::with_max_channels(2)));
+
+        let placements: Vec<Placement> = (0..4)
+            .map(|i| pool.assign_channel(hrm(i), None).unwrap())
+            .collect();
+        assert_eq!(
+            placements,
+            vec![
+                Placement {
+                    node: a,
+                    channel: 0
+                },
+                Placement {
+                    node: b,
+                    channel: 0
+                },
+                Placement {
+                    node: a,
+                    channel: 1
+                },
+                Placement {
+                    node: b,
+                    channel: 1
+                },
+            ]
+        );
+
+        assert_eq!(
+            pool.assign_channel(hrm(4), None),
+            Err(Error::NoAvailableChannel)
+        );
+    }
+
+    #[test]
+    fn it_reassigns_channels_from_removed_node() {
+        let hrm_id = ChannelID {
+            device_number: 47330,
+            device_type: 120,
+            transmission_type: 1,
+        };
+        let first = Emulator::with_max_channels(2);
+        let second = Emulator::with_max_channels(2);
+        second.add_sensor(hrm_id, vec![[4, 27, 222, 94, 173, 98, 26, 63]]);
+
+        let mut pool = NodePool::new();
+        let a = pool.add_node(open_node(&first));
+        let b = pool.add_node(open_node(&second));
+
+        let (device, receiver) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: hrm_id.device_number,
+            transmission_type: hrm_id.transmission_type,
+        });
+        let placement = pool.assign_channel(Box::new(device), None).unwrap();
+        assert_eq!(
+            placement,
+            Placement {
+                node: a,
+                channel: 0
+            }
+        );
+        pool.assign_channel(hrm(1), None).unwrap();
+        let (search, _) = pool.search(None).unwrap();
+        assert_eq!(
+            search,
+            Placement {
+                node: a,
+                channel: 1
+            }
+        );
+
+        let reassignments = pool.remove_node(a).unwrap();
+        assert_eq!(
+            reassignments,
+            vec![Reassignment {
+                from: placement,
+                to: Ok(Placement {
+                    node: b,
+                    channel: 1
+                }),
+            }]
+        );
+        assert_eq!(pool.node_ids(), vec![b]);
+        assert!(second.channel_open(1));
+        assert!(!first
+            .received()
+            .iter()
+            .any(|m| matches!(m, Message::CloseChannel(_))));
+
+        second.tick();
+        let data = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(data.computed_heart_rate, 63);
+    }
+
+    #[test]
+    fn it_reassigns_channels_from_disconnected_node() {
+        let hrm_id = ChannelID {
+            device_number: 47330,
+            device_type: 120,
+            transmission_type: 1,
+        };
+        let first = Emulator::with_max_channels(2);
+        let second = Emulator::with_max_channels(2);
+        second.add_sensor(hrm_id, vec![[4, 27, 222, 94, 173, 98, 26, 63]]);
+
+        let mut pool = NodePool::new();
+        let a = pool.add_node(open_node(&first));
+        let b = pool.add_node(open_node(&second));
+
+        let (device, receiver) = heart_rate_monitor::new_paired(DevicePairing {
+            device_id: hrm_id.device_number,
+            transmission_type: hrm_id.transmission_type,
+        });
+        let placement = pool.assign_channel(Box::new(device), None).unwrap();
+        assert_eq!(placement.node, a);
+
+        first.unplug();
+        assert_eq!(
+            pool.reassignments().recv_timeout(Duration::from_secs(1)),
+            Ok(Reassignment {
+                from: placement,
+                to: Ok(Placement {
+                    node: b,
+                    channel: 0
+                }),
+            })
+        );
+        assert_eq!(pool.node_ids(), vec![b]);
+
+        second.tick();
+        let data = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(data.computed_heart_rate, 63);
+    }
+
+    #[test]
+    fn it_skips_nodes_not_opened() {
+        let mut pool = NodePool::new();
+        pool.add_node(NodeBuilder::new(KEY).transport(Emulator::new()).build());
+        let opened = pool.add_node(open_node(&Emulator::new()));
+
+        assert_eq!(
+            pool.assign_channel(hrm(1), None),
+            Ok(Placement {
+                node: opened,
+                channel: 0
+            })
+        );
+    }
+
+    #[test]
+    fn it_rejects_unknown_node() {
+        let mut pool = NodePool::new();
+        assert_eq!(pool.remove_node(0).err(), Some(Error::NodeNotFound));
+        assert_eq!(
+            pool.close_channel(Placement {
+                node: 0,
+                channel: 0
+            }),
+            Err(Error::NodeNotFound)
+        );
+    }
+}
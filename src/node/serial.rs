@@ -0,0 +1,195 @@
+use core::time::Duration;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+use super::{Error, Reader, Transport};
+use crate::message::Message;
+
+/// Default baud rate of ANT modules in asynchronous serial mode.
+pub const DEFAULT_BAUD_RATE: u32 = 57600;
+
+impl From<serialport::Error> for Error {
+    fn from(value: serialport::Error) -> Self {
+        match value.kind() {
+            serialport::ErrorKind::Io(std::io::ErrorKind::TimedOut) => Error::Timeout,
+            kind => Error::SerialPortError(kind),
+        }
+    }
+}
+
+/// Map an error reading or writing the serial port.
+fn io_error(value: std::io::Error) -> Error {
+    match value.kind() {
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => Error::Timeout,
+        kind => Error::SerialPortError(serialport::ErrorKind::Io(kind)),
+    }
+}
+
+/// Transport for ANT modules connected to a serial device, e.g. an nRF or AP2 module on a UART.
+///
+/// Modules must be in asynchronous serial mode, selected on the module by its PORTSEL pin. Byte
+/// synchronous mode needs the module's SRDY and SEN handshake lines driven for every byte, which
+/// serial devices can't do.
+///
+/// Messages use the same framing as USB sticks, so decoding is handled by the node's usual
+/// `reader::Publisher`.
+pub struct SerialTransport {
+    path: String,
+    baud_rate: u32,
+    flow_control: bool,
+    reader: Mutex<Option<Box<dyn serialport::SerialPort>>>,
+    writer: Mutex<Option<Box<dyn serialport::SerialPort>>>,
+}
+
+impl SerialTransport {
+    /// Create a transport for an asynchronous serial module at the default baud rate.
+    pub fn new(path: &str) -> SerialTransport {
+        SerialTransport::with_options(path, DEFAULT_BAUD_RATE, false)
+    }
+
+    /// Create a transport at the given baud rate. The module's RTS line signals when it can't
+    /// accept data, so hardware flow control can be enabled when it's wired to the host's CTS.
+    pub fn with_options(path: &str, baud_rate: u32, flow_control: bool) -> SerialTransport {
+        SerialTransport {
+            path: path.to_string(),
+            baud_rate,
+            flow_control,
+            reader: Mutex::new(None),
+            writer: Mutex::new(None),
+        }
+    }
+}
+
+impl Transport for SerialTransport {
+    fn open(&self) -> Result<(), Error> {
+        let flow_control = if self.flow_control {
+            serialport::FlowControl::Hardware
+        } else {
+            serialport::FlowControl::None
+        };
+
+        let port = serialport::new(&self.path, self.baud_rate)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(serialport::Parity::None)
+            .stop_bits(serialport::StopBits::One)
+            .flow_control(flow_control)
+            .open()?;
+        port.clear(serialport::ClearBuffer::All)?;
+
+        let writer = port.try_clone()?;
+
+        *self.reader.lock().unwrap() = Some(port);
+        *self.writer.lock().unwrap() = Some(writer);
+
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        // Serial modules have no bus level reset, so reset with the ResetSystem command instead
+        self.write(&Message::ResetSystem.encode(), Duration::from_millis(100))?;
+        Ok(())
+    }
+
+    fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+        let mut writer = self.writer.lock().unwrap();
+        let port = writer.as_mut().ok_or(Error::HandleNotInitialized)?;
+        port.set_timeout(timeout)?;
+
+        port.write_all(buf).map_err(io_error)?;
+        port.flush().map_err(io_error)?;
+
+        Ok(buf.len())
+    }
+}
+
+impl Reader for SerialTransport {
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+        let mut reader = self.reader.lock().unwrap();
+        let port = reader.as_mut().ok_or(Error::HandleNotInitialized)?;
+        port.set_timeout(timeout)?;
+        port.read(buf).map_err(io_error)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::io::{Read, Write};
+    use std::thread;
+
+    use serialport::{SerialPort, TTYPort};
+
+    use super::*;
+    use crate::message::{self, reader, MessageID, SerialErrorData, SerialErrorKind};
+
+    fn open_pair() -> (TTYPort, TTYPort, SerialTransport) {
+        let (mut module, host) = TTYPort::pair().expect("should create pseudo-terminal pair");
+        module.set_timeout(Duration::from_secs(1)).unwrap();
+        let path = host.name().expect("pseudo-terminal should have a name");
+        let transport = SerialTransport::new(&path);
+        transport.open().expect("transport should open");
+        (module, host, transport)
+    }
+
+    #[test]
+    fn it_writes_messages() {
+        let (mut module, _host, transport) = open_pair();
+
+        let encoded = Message::ResetSystem.encode();
+        assert_eq!(
+            transport.write(&encoded, Duration::from_millis(100)),
+            Ok(encoded.len())
+        );
+
+        let mut buf = vec![0u8; encoded.len()];
+        module.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, encoded);
+    }
+
+    #[test]
+    fn it_publishes_messages_read_from_module() {
+        let (mut module, _host, transport) = open_pair();
+
+        let startup = Message::StartupMessage(message::StartupMessageData { reason: 0x20 });
+        let serial_error = Message::SerialError(SerialErrorData {
+            error: SerialErrorKind::IncorrectChecksum,
+        });
+        let mut bytes = startup.encode();
+        bytes.extend(serial_error.encode());
+        module.write_all(&bytes).unwrap();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let publisher = reader::Publisher::new(&transport, sender, 256);
+        thread::scope(|s| {
+            s.spawn(|| publisher.run());
+
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(1)), Ok(startup));
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(1)),
+                Ok(serial_error)
+            );
+
+            publisher.stop();
+        });
+    }
+
+    #[test]
+    fn it_times_out_reading() {
+        let (_module, _host, transport) = open_pair();
+
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            transport.read(&mut buf, Duration::from_millis(10)),
+            Err(Error::Timeout)
+        );
+    }
+
+    #[test]
+    fn it_reset_sends_reset_system() {
+        let (mut module, _host, transport) = open_pair();
+        transport.reset().unwrap();
+
+        let mut buf = [0u8; 5];
+        module.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[2], MessageID::ResetSystem.into());
+    }
+}
@@ -0,0 +1,259 @@
+use core::time::Duration;
+use std::sync::RwLock;
+
+use super::{Error, Reader, Transport};
+
+pub const DYNASTREAM_INNOVATIONS_VID: u16 = 0xfcf;
+pub const DI_ANTUSB2_STICK: u16 = 0x1008;
+/// ANTUSB-m stick, also sold as the ANT-M or mini ANT+ stick.
+pub const DI_ANT_M_STICK: u16 = 0x1009;
+
+/// Product IDs of the Dynastream Innovations USB sticks supported by `UsbTransport`.
+pub const SUPPORTED_PRODUCTS: [u16; 2] = [DI_ANTUSB2_STICK, DI_ANT_M_STICK];
+
+/// A USB stick attached to the host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StickInfo {
+    pub bus_number: u8,
+    pub address: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Serial number string descriptor, if the device could be opened to read it
+    pub serial_number: Option<String>,
+}
+
+/// Selects which stick to open when more than one is attached.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StickSelector {
+    /// The first supported stick found
+    First,
+    /// The supported stick at the given USB bus number and address
+    BusAddress { bus_number: u8, address: u8 },
+    /// The supported stick with the given serial number
+    SerialNumber(String),
+}
+
+impl StickSelector {
+    pub fn matches(&self, stick: &StickInfo) -> bool {
+        match self {
+            StickSelector::First => true,
+            StickSelector::BusAddress {
+                bus_number,
+                address,
+            } => stick.bus_number == *bus_number && stick.address == *address,
+            StickSelector::SerialNumber(serial_number) => {
+                stick.serial_number.as_ref() == Some(serial_number)
+            }
+        }
+    }
+}
+
+/// List all supported USB sticks attached to the host.
+pub fn list_sticks() -> Result<Vec<StickInfo>, Error> {
+    Ok(supported_devices()?
+        .into_iter()
+        .map(|(_, stick)| stick)
+        .collect())
+}
+
+fn supported_devices() -> Result<Vec<(rusb::Device<rusb::GlobalContext>, StickInfo)>, Error> {
+    let mut result = vec![];
+
+    for device in rusb::devices()?.iter() {
+        let descriptor = device.device_descriptor()?;
+
+        if descriptor.vendor_id() == DYNASTREAM_INNOVATIONS_VID
+            && SUPPORTED_PRODUCTS.contains(&descriptor.product_id())
+        {
+            // Reading the serial number requires permission to open the device, so it's
+            // optional rather than failing the whole listing
+            let serial_number = device
+                .open()
+                .and_then(|handle| handle.read_serial_number_string_ascii(&descriptor))
+                .ok();
+
+            let stick = StickInfo {
+                bus_number: device.bus_number(),
+                address: device.address(),
+                vendor_id: descriptor.vendor_id(),
+                product_id: descriptor.product_id(),
+                serial_number,
+            };
+            result.push((device, stick));
+        }
+    }
+
+    Ok(result)
+}
+
+#[derive(Clone, Copy)]
+struct Endpoint {
+    interface: u8,
+    address: u8,
+}
+
+struct Connection {
+    handle: rusb::DeviceHandle<rusb::GlobalContext>,
+    in_ep: Endpoint,
+    out_ep: Endpoint,
+}
+
+/// Transport for ANT USB sticks, communicating over the stick's bulk endpoints.
+pub struct UsbTransport {
+    selector: StickSelector,
+    connection: RwLock<Option<Connection>>,
+}
+
+impl UsbTransport {
+    pub fn new(selector: StickSelector) -> UsbTransport {
+        UsbTransport {
+            selector,
+            connection: RwLock::new(None),
+        }
+    }
+
+    fn find_device(&self) -> Result<rusb::Device<rusb::GlobalContext>, Error> {
+        supported_devices()?
+            .into_iter()
+            .find(|(_, stick)| self.selector.matches(stick))
+            .map(|(device, _)| device)
+            .ok_or(Error::DeviceNotFound)
+    }
+
+    fn find_endpoints(
+        &self,
+        device: &rusb::Device<rusb::GlobalContext>,
+    ) -> Result<(Endpoint, Endpoint), Error> {
+        let config = device.config_descriptor(0)?;
+
+        let interfaces = config.interfaces();
+
+        let mut in_endpoint = None;
+        let mut out_endpoint = None;
+
+        for interface in interfaces {
+            for descriptor in interface.descriptors() {
+                for endpoint in descriptor.endpoint_descriptors() {
+                    if endpoint.usage_type() == rusb::UsageType::Data
+                        && endpoint.transfer_type() == rusb::TransferType::Bulk
+                    {
+                        let result = Some(Endpoint {
+                            interface: interface.number(),
+                            address: endpoint.address(),
+                        });
+
+                        match endpoint.direction() {
+                            rusb::Direction::In => in_endpoint = result,
+                            rusb::Direction::Out => out_endpoint = result,
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(in_ep) = in_endpoint {
+            if let Some(out_ep) = out_endpoint {
+                return Ok((in_ep, out_ep));
+            }
+        }
+
+        Err(Error::EndpointNotFound)
+    }
+}
+
+impl Transport for UsbTransport {
+    fn open(&self) -> Result<(), Error> {
+        let device = self.find_device()?;
+        let (in_ep, out_ep) = self.find_endpoints(&device)?;
+
+        let mut handle = device.open()?;
+
+        handle.set_auto_detach_kernel_driver(true)?;
+        handle.set_active_configuration(0)?;
+        handle.claim_interface(in_ep.interface)?;
+        if in_ep.interface != out_ep.interface {
+            handle.claim_interface(out_ep.interface)?;
+        }
+
+        let mut connection = self.connection.write().unwrap();
+        *connection = Some(Connection {
+            handle,
+            in_ep,
+            out_ep,
+        });
+
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        let mut connection = self.connection.write().unwrap();
+        if let Some(ref mut connection) = *connection {
+            connection.handle.reset()?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+        let connection = self.connection.read().unwrap();
+        let connection = connection.as_ref().ok_or(Error::HandleNotInitialized)?;
+        Ok(connection
+            .handle
+            .write_bulk(connection.out_ep.address, buf, timeout)?)
+    }
+}
+
+impl Reader for UsbTransport {
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+        let connection = self.connection.read().unwrap();
+        let connection = connection.as_ref().ok_or(Error::HandleNotInitialized)?;
+        Ok(connection
+            .handle
+            .read_bulk(connection.in_ep.address, buf, timeout)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STICK: StickInfo = StickInfo {
+        bus_number: 1,
+        address: 4,
+        vendor_id: DYNASTREAM_INNOVATIONS_VID,
+        product_id: DI_ANTUSB2_STICK,
+        serial_number: None,
+    };
+
+    #[test]
+    fn it_selects_first_stick() {
+        assert!(StickSelector::First.matches(&STICK));
+    }
+
+    #[test]
+    fn it_selects_stick_by_bus_address() {
+        let selector = StickSelector::BusAddress {
+            bus_number: 1,
+            address: 4,
+        };
+        assert!(selector.matches(&STICK));
+
+        let selector = StickSelector::BusAddress {
+            bus_number: 1,
+            address: 5,
+        };
+        assert!(!selector.matches(&STICK));
+    }
+
+    #[test]
+    fn it_selects_stick_by_serial_number() {
+        let selector = StickSelector::SerialNumber("123".to_string());
+        assert!(!selector.matches(&STICK));
+
+        let stick = StickInfo {
+            serial_number: Some("123".to_string()),
+            ..STICK
+        };
+        assert!(selector.matches(&stick));
+    }
+}
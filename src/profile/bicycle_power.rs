@@ -0,0 +1,647 @@
+use std::f64::consts::PI;
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    Calibration = 0x01,
+    PowerOnly = 0x10,
+    WheelTorque = 0x11,
+    CrankTorque = 0x12,
+    TorqueEffectivenessAndPedalSmoothness = 0x13,
+    CrankTorqueFrequency = 0x20,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum CalibrationID {
+    CrankTorqueFrequency = 0x10,
+    Request = 0xaa,
+    AutoZeroConfiguration = 0xab,
+    ResponseSuccess = 0xac,
+    ResponseFailure = 0xaf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutoZeroStatus {
+    Off,
+    On,
+    NotSupported,
+}
+
+impl TryFrom<u8> for AutoZeroStatus {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AutoZeroStatus::Off),
+            1 => Ok(AutoZeroStatus::On),
+            0xff => Ok(AutoZeroStatus::NotSupported),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+/// Create a manual calibration (zero offset) request message.
+pub fn calibration_request_message(channel: u8) -> message::Message {
+    message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([
+            PageNumber::Calibration.into(),
+            CalibrationID::Request.into(),
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+        ]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    })
+}
+
+/// Create a message enabling or disabling the power meter's auto zero feature.
+pub fn auto_zero_configuration_message(channel: u8, enabled: bool) -> message::Message {
+    message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([
+            PageNumber::Calibration.into(),
+            CalibrationID::AutoZeroConfiguration.into(),
+            enabled.into(),
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+        ]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    })
+}
+
+/// Accumulated values of the last torque page received, to compute averages from.
+#[derive(Clone, Copy, Debug)]
+struct TorqueEvent {
+    update_event_count: u8,
+    ticks: u8,
+    period: u16,
+    accumulated_torque: u16,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CrankTorqueFrequencyEvent {
+    update_event_count: u8,
+    time_stamp: u16,
+    torque_ticks_stamp: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct BicyclePower {
+    pairing: DevicePairing,
+
+    power_only: Option<(u8, u16)>,
+    wheel_torque: Option<TorqueEvent>,
+    crank_torque: Option<TorqueEvent>,
+    crank_torque_frequency: Option<CrankTorqueFrequencyEvent>,
+    /// Crank torque frequency zero offset in Hz, from the last calibration
+    zero_offset: Option<u16>,
+
+    sender: crossbeam_channel::Sender<BicyclePowerData>,
+}
+
+pub fn new_search() -> (BicyclePower, crossbeam_channel::Receiver<BicyclePowerData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(
+    pairing: DevicePairing,
+) -> (BicyclePower, crossbeam_channel::Receiver<BicyclePowerData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    let power = BicyclePower {
+        pairing,
+
+        power_only: None,
+        wheel_torque: None,
+        crank_torque: None,
+        crank_torque_frequency: None,
+        zero_offset: None,
+
+        sender,
+    };
+
+    (power, receiver)
+}
+
+impl BicyclePower {
+    /// Set the crank torque frequency zero offset in Hz, e.g. from a previous calibration. The
+    /// offset is updated by calibration responses from the power meter.
+    pub fn set_zero_offset(&mut self, offset: u16) {
+        self.zero_offset = Some(offset);
+    }
+
+    fn process_calibration(&mut self, data: [u8; 8]) -> Result<CalibrationData, Error> {
+        let calibration_id: CalibrationID = data[1].try_into()?;
+        Ok(match calibration_id {
+            CalibrationID::Request => CalibrationData::Request,
+            CalibrationID::AutoZeroConfiguration => CalibrationData::AutoZeroConfiguration {
+                auto_zero_status: data[2].try_into()?,
+            },
+            CalibrationID::ResponseSuccess | CalibrationID::ResponseFailure => {
+                CalibrationData::Response {
+                    success: calibration_id == CalibrationID::ResponseSuccess,
+                    auto_zero_status: data[2].try_into()?,
+                    calibration_data: i16::from_le_bytes([data[6], data[7]]),
+                }
+            }
+            CalibrationID::CrankTorqueFrequency => match data[2] {
+                // Crank torque frequency values are big endian
+                0x01 => {
+                    let offset = u16::from_be_bytes([data[6], data[7]]);
+                    self.zero_offset = Some(offset);
+                    CalibrationData::ZeroOffset(offset)
+                }
+                0x02 => {
+                    CalibrationData::SlopeAcknowledgement(u16::from_be_bytes([data[3], data[4]]))
+                }
+                0x03 => CalibrationData::SerialNumberAcknowledgement(u16::from_be_bytes([
+                    data[3], data[4],
+                ])),
+                _ => return Err(Error::InvalidValue),
+            },
+        })
+    }
+
+    fn process_power_only(&mut self, data: [u8; 8]) -> PowerOnlyData {
+        let update_event_count = data[1];
+        let accumulated_power = u16::from_le_bytes([data[4], data[5]]);
+
+        let average_power = self.power_only.and_then(|(last_count, last_power)| {
+            let events = update_event_count.wrapping_sub(last_count);
+            (events > 0)
+                .then(|| f64::from(accumulated_power.wrapping_sub(last_power)) / f64::from(events))
+        });
+        self.power_only = Some((update_event_count, accumulated_power));
+
+        PowerOnlyData {
+            update_event_count,
+            pedal_power: match data[2] {
+                0xff => None,
+                value => Some(PedalPower {
+                    percentage: value & 0x7f,
+                    right_pedal: value & 0x80 != 0,
+                }),
+            },
+            instantaneous_cadence: cadence(data[3]),
+            accumulated_power,
+            instantaneous_power: u16::from_le_bytes([data[6], data[7]]),
+            average_power,
+        }
+    }
+
+    fn process_torque(&mut self, data: [u8; 8]) -> TorqueData {
+        let event = TorqueEvent {
+            update_event_count: data[1],
+            ticks: data[2],
+            period: u16::from_le_bytes([data[4], data[5]]),
+            accumulated_torque: u16::from_le_bytes([data[6], data[7]]),
+        };
+
+        let last = if data[0] == u8::from(PageNumber::WheelTorque) {
+            self.wheel_torque.replace(event)
+        } else {
+            self.crank_torque.replace(event)
+        };
+
+        let mut result = TorqueData {
+            update_event_count: event.update_event_count,
+            ticks: event.ticks,
+            instantaneous_cadence: cadence(data[3]),
+            period: event.period,
+            accumulated_torque: event.accumulated_torque,
+            average_torque: None,
+            average_power: None,
+            average_cadence: None,
+        };
+
+        if let Some(last) = last {
+            let events = event
+                .update_event_count
+                .wrapping_sub(last.update_event_count);
+            let period = event.period.wrapping_sub(last.period);
+            let torque = f64::from(
+                event
+                    .accumulated_torque
+                    .wrapping_sub(last.accumulated_torque),
+            );
+
+            if events > 0 {
+                result.average_torque = Some(torque / (32.0 * f64::from(events)));
+            }
+            if period > 0 {
+                result.average_power = Some(128.0 * PI * torque / f64::from(period));
+                // Each event is a crank revolution for crank torque sensors, while wheel torque
+                // sensors only report cadence instantaneously
+                if data[0] == u8::from(PageNumber::CrankTorque) {
+                    result.average_cadence =
+                        Some(60.0 * f64::from(events) * 2048.0 / f64::from(period));
+                }
+            }
+        }
+
+        result
+    }
+
+    fn process_crank_torque_frequency(&mut self, data: [u8; 8]) -> CrankTorqueFrequencyData {
+        // Crank torque frequency values are big endian
+        let event = CrankTorqueFrequencyEvent {
+            update_event_count: data[1],
+            time_stamp: u16::from_be_bytes([data[4], data[5]]),
+            torque_ticks_stamp: u16::from_be_bytes([data[6], data[7]]),
+        };
+        let slope = u16::from_be_bytes([data[2], data[3]]);
+
+        let mut result = CrankTorqueFrequencyData {
+            update_event_count: event.update_event_count,
+            slope,
+            time_stamp: event.time_stamp,
+            torque_ticks_stamp: event.torque_ticks_stamp,
+            average_cadence: None,
+            average_torque: None,
+            average_power: None,
+        };
+
+        if let Some(last) = self.crank_torque_frequency.replace(event) {
+            let events = event
+                .update_event_count
+                .wrapping_sub(last.update_event_count);
+            let time = event.time_stamp.wrapping_sub(last.time_stamp);
+            let ticks = event
+                .torque_ticks_stamp
+                .wrapping_sub(last.torque_ticks_stamp);
+
+            if events > 0 && time > 0 {
+                // Time stamps are in 1/2000 seconds
+                let cadence_period = f64::from(time) / f64::from(events) / 2000.0;
+                let cadence = 60.0 / cadence_period;
+                result.average_cadence = Some(cadence);
+
+                if let Some(offset) = self.zero_offset.filter(|_| slope > 0) {
+                    let torque_frequency =
+                        f64::from(ticks) / (f64::from(time) / 2000.0) - f64::from(offset);
+                    let torque = torque_frequency / (f64::from(slope) / 10.0);
+                    result.average_torque = Some(torque);
+                    result.average_power = Some(torque * cadence * PI / 30.0);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+fn cadence(value: u8) -> Option<u8> {
+    match value {
+        0xff => None,
+        cadence => Some(cadence),
+    }
+}
+
+/// Percentage in 0.5% increments, where 0xff is invalid.
+fn half_percent(value: u8) -> Option<f64> {
+    match value {
+        0xff => None,
+        value => Some(f64::from(value) / 2.0),
+    }
+}
+
+impl Device for BicyclePower {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        11
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8182
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for BicyclePower {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::Calibration) => {
+                    BicyclePowerData::Calibration(self.process_calibration(data)?)
+                }
+                Ok(PageNumber::PowerOnly) => {
+                    BicyclePowerData::PowerOnly(self.process_power_only(data))
+                }
+                Ok(PageNumber::WheelTorque) => {
+                    BicyclePowerData::WheelTorque(self.process_torque(data))
+                }
+                Ok(PageNumber::CrankTorque) => {
+                    BicyclePowerData::CrankTorque(self.process_torque(data))
+                }
+                Ok(PageNumber::TorqueEffectivenessAndPedalSmoothness) => {
+                    BicyclePowerData::TorqueEffectivenessAndPedalSmoothness(
+                        TorqueEffectivenessData {
+                            update_event_count: data[1],
+                            left_torque_effectiveness: half_percent(data[2]),
+                            right_torque_effectiveness: half_percent(data[3]),
+                            left_pedal_smoothness: half_percent(data[4]),
+                            right_pedal_smoothness: match data[5] {
+                                0xfe => None,
+                                value => half_percent(value),
+                            },
+                            combined_pedal_smoothness: data[5] == 0xfe,
+                        },
+                    )
+                }
+                Ok(PageNumber::CrankTorqueFrequency) => BicyclePowerData::CrankTorqueFrequency(
+                    self.process_crank_torque_frequency(data),
+                ),
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => BicyclePowerData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CalibrationData {
+    /// Manual zero calibration request, sent by displays
+    Request,
+    AutoZeroConfiguration {
+        auto_zero_status: AutoZeroStatus,
+    },
+    Response {
+        success: bool,
+        auto_zero_status: AutoZeroStatus,
+        calibration_data: i16,
+    },
+    /// Crank torque frequency zero offset in Hz
+    ZeroOffset(u16),
+    /// Crank torque frequency slope saved by the sensor, in 1/10 Nm/Hz
+    SlopeAcknowledgement(u16),
+    SerialNumberAcknowledgement(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PedalPower {
+    pub percentage: u8,
+    /// Whether the percentage is of the right pedal, rather than unknown
+    pub right_pedal: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerOnlyData {
+    pub update_event_count: u8,
+    pub pedal_power: Option<PedalPower>,
+    pub instantaneous_cadence: Option<u8>,
+    /// measured in watts, wraps around at 65536W
+    pub accumulated_power: u16,
+    pub instantaneous_power: u16,
+
+    /// Average power in watts since the last page
+    pub average_power: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TorqueData {
+    pub update_event_count: u8,
+    /// Wheel or crank revolutions
+    pub ticks: u8,
+    pub instantaneous_cadence: Option<u8>,
+    /// measured in 1/2048s, wraps around at 32s
+    pub period: u16,
+    /// measured in 1/32Nm, wraps around at 2048Nm
+    pub accumulated_torque: u16,
+
+    /// Average torque in Nm since the last page
+    pub average_torque: Option<f64>,
+    /// Average power in watts since the last page
+    pub average_power: Option<f64>,
+    /// Average cadence in revolutions per minute since the last page, for crank torque only
+    pub average_cadence: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TorqueEffectivenessData {
+    pub update_event_count: u8,
+    /// Percentages
+    pub left_torque_effectiveness: Option<f64>,
+    pub right_torque_effectiveness: Option<f64>,
+    pub left_pedal_smoothness: Option<f64>,
+    pub right_pedal_smoothness: Option<f64>,
+    /// Whether the left pedal smoothness is combined smoothness of both pedals
+    pub combined_pedal_smoothness: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrankTorqueFrequencyData {
+    pub update_event_count: u8,
+    /// measured in 1/10 Nm/Hz
+    pub slope: u16,
+    /// measured in 1/2000s
+    pub time_stamp: u16,
+    pub torque_ticks_stamp: u16,
+
+    /// Average cadence in revolutions per minute since the last page
+    pub average_cadence: Option<f64>,
+    /// Average torque in Nm since the last page, once the zero offset is known
+    pub average_torque: Option<f64>,
+    /// Average power in watts since the last page, once the zero offset is known
+    pub average_power: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BicyclePowerData {
+    Calibration(CalibrationData),
+    PowerOnly(PowerOnlyData),
+    WheelTorque(TorqueData),
+    CrankTorque(TorqueData),
+    TorqueEffectivenessAndPedalSmoothness(TorqueEffectivenessData),
+    CrankTorqueFrequency(CrankTorqueFrequencyData),
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::test::payload;
+
+    fn process(power: &mut BicyclePower, data: [u8; 8]) {
+        assert_eq!(power.process_data(payload(data)), Ok(()));
+    }
+
+    #[test]
+    fn it_processes_power_only_page() {
+        let (mut power, receiver) = new_search();
+        process(&mut power, [0x10, 1, 0xb2, 90, 100, 0, 100, 0]);
+        process(&mut power, [0x10, 3, 0xb2, 92, 0x2c, 0x01, 110, 0]);
+
+        let data: Vec<BicyclePowerData> = receiver.try_iter().collect();
+        assert!(matches!(
+            data[0],
+            BicyclePowerData::PowerOnly(PowerOnlyData {
+                average_power: None,
+                ..
+            })
+        ));
+        assert_eq!(
+            data[1],
+            BicyclePowerData::PowerOnly(PowerOnlyData {
+                update_event_count: 3,
+                pedal_power: Some(PedalPower {
+                    percentage: 50,
+                    right_pedal: true,
+                }),
+                instantaneous_cadence: Some(92),
+                accumulated_power: 300,
+                instantaneous_power: 110,
+                average_power: Some(100.0),
+            })
+        );
+    }
+
+    #[test]
+    fn it_processes_crank_torque_page() {
+        let (mut power, receiver) = new_search();
+        // 20Nm at 90rpm, with the event count and period rolling over
+        process(&mut power, [0x12, 0xff, 1, 90, 0x00, 0xff, 0, 0]);
+        let [period_lsb, period_msb] = 0xff00u16.wrapping_add(1365).to_le_bytes();
+        let [torque_lsb, torque_msb] = 640u16.to_le_bytes();
+        process(
+            &mut power,
+            [
+                0x12, 0, 2, 90, period_lsb, period_msb, torque_lsb, torque_msb,
+            ],
+        );
+
+        let BicyclePowerData::CrankTorque(data) = receiver.try_iter().last().unwrap() else {
+            panic!("expected crank torque page");
+        };
+        assert_eq!(data.average_torque, Some(20.0));
+        assert!((data.average_cadence.unwrap() - 90.0).abs() < 0.1);
+        assert!((data.average_power.unwrap() - 188.5).abs() < 0.5);
+    }
+
+    #[test]
+    fn it_processes_wheel_torque_page() {
+        let (mut power, receiver) = new_search();
+        process(&mut power, [0x11, 1, 10, 0xff, 0, 0, 0, 0]);
+        process(&mut power, [0x11, 2, 11, 0xff, 0x00, 0x02, 0x40, 0x01]);
+
+        let BicyclePowerData::WheelTorque(data) = receiver.try_iter().last().unwrap() else {
+            panic!("expected wheel torque page");
+        };
+        assert_eq!(data.instantaneous_cadence, None);
+        assert_eq!(data.average_torque, Some(10.0));
+        assert_eq!(data.average_cadence, None);
+        // 10Nm at 4 wheel revolutions per second
+        assert!((data.average_power.unwrap() - 10.0 * 8.0 * PI).abs() < 0.01);
+    }
+
+    #[test]
+    fn it_processes_torque_effectiveness_page() {
+        let (mut power, receiver) = new_search();
+        process(&mut power, [0x13, 5, 150, 0xff, 60, 0xfe, 0xff, 0xff]);
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            BicyclePowerData::TorqueEffectivenessAndPedalSmoothness(TorqueEffectivenessData {
+                update_event_count: 5,
+                left_torque_effectiveness: Some(75.0),
+                right_torque_effectiveness: None,
+                left_pedal_smoothness: Some(30.0),
+                right_pedal_smoothness: None,
+                combined_pedal_smoothness: true,
+            })
+        );
+    }
+
+    #[test]
+    fn it_processes_crank_torque_frequency_after_calibration() {
+        let (mut power, receiver) = new_search();
+        process(&mut power, [0x01, 0x10, 0x01, 0xff, 0xff, 0xff, 0x01, 0xf4]);
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            BicyclePowerData::Calibration(CalibrationData::ZeroOffset(500))
+        );
+
+        // Slope of 10Nm/Hz, and a torque frequency of 700Hz over one revolution of 2/3 seconds
+        process(&mut power, [0x20, 1, 0x00, 100, 0x00, 0x00, 0x00, 0x00]);
+        process(&mut power, [0x20, 2, 0x00, 100, 0x05, 0x35, 0x01, 0xd2]);
+
+        let BicyclePowerData::CrankTorqueFrequency(data) = receiver.try_iter().last().unwrap()
+        else {
+            panic!("expected crank torque frequency page");
+        };
+        assert!((data.average_cadence.unwrap() - 90.0).abs() < 0.1);
+        assert!((data.average_torque.unwrap() - 20.0).abs() < 0.1);
+        assert!((data.average_power.unwrap() - 188.5).abs() < 1.0);
+    }
+
+    #[test]
+    fn it_processes_calibration_response() {
+        let (mut power, receiver) = new_search();
+        process(&mut power, [0x01, 0xac, 0x01, 0xff, 0xff, 0xff, 0x9c, 0xff]);
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            BicyclePowerData::Calibration(CalibrationData::Response {
+                success: true,
+                auto_zero_status: AutoZeroStatus::On,
+                calibration_data: -100,
+            })
+        );
+    }
+
+    #[test]
+    fn it_creates_calibration_request() {
+        assert_eq!(
+            calibration_request_message(2),
+            message::Message::AcknowledgedData(message::DataPayload {
+                channel: 2,
+                data: Some([0x01, 0xaa, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            })
+        );
+    }
+}
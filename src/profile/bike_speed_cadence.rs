@@ -0,0 +1,472 @@
+use std::time::Duration;
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message::{self, common::BatteryStatus};
+use log::warn;
+
+/// Default wheel circumference in millimetres, of a 700x23c tyre.
+pub const DEFAULT_WHEEL_CIRCUMFERENCE: u16 = 2096;
+
+/// Number of messages without a new event after which the bike is considered stopped, around
+/// 3 seconds at the sensors' 4Hz message rate.
+const STOP_MESSAGES: u8 = 12;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum SensorType {
+    SpeedAndCadence = 121,
+    Cadence = 122,
+    Speed = 123,
+}
+
+impl SensorType {
+    fn channel_period(&self) -> u16 {
+        match self {
+            SensorType::SpeedAndCadence => 8086,
+            SensorType::Cadence => 8102,
+            SensorType::Speed => 8118,
+        }
+    }
+}
+
+/// Tracks cumulative revolution events to compute revolution rates.
+#[derive(Clone, Debug, Default)]
+struct EventTracker {
+    last: Option<(u16, u16)>,
+    unchanged: u8,
+    revolutions_per_second: Option<f64>,
+}
+
+impl EventTracker {
+    /// Update with the latest event time and cumulative revolution count, returning the
+    /// revolution rate and whether the revolutions have stopped.
+    fn update(&mut self, event_time: u16, revolutions: u16) -> (Option<f64>, bool) {
+        let Some((last_time, last_revolutions)) = self.last else {
+            self.last = Some((event_time, revolutions));
+            return (None, false);
+        };
+
+        if event_time == last_time {
+            self.unchanged = self.unchanged.saturating_add(1);
+            if self.unchanged >= STOP_MESSAGES {
+                self.revolutions_per_second = Some(0.0);
+            }
+        } else {
+            // Event times and revolution counts roll over, so deltas wrap
+            let time = f64::from(event_time.wrapping_sub(last_time)) / 1024.0;
+            let count = f64::from(revolutions.wrapping_sub(last_revolutions));
+            self.revolutions_per_second = Some(count / time);
+            self.unchanged = 0;
+            self.last = Some((event_time, revolutions));
+        }
+
+        (self.revolutions_per_second, self.unchanged >= STOP_MESSAGES)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BikeSpeedCadence {
+    sensor_type: SensorType,
+    pairing: DevicePairing,
+    wheel_circumference: u16,
+
+    page: Option<u8>,
+    page_toggle_observed: bool,
+    speed: EventTracker,
+    cadence: EventTracker,
+
+    sender: crossbeam_channel::Sender<BikeSpeedCadenceData>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BikeSpeedCadenceData {
+    /// Page number, or None for combined speed and cadence sensors which have a single page
+    pub page: Option<u8>,
+
+    pub cadence_event_time: Option<u16>,
+    pub cumulative_cadence_revolutions: Option<u16>,
+    pub speed_event_time: Option<u16>,
+    pub cumulative_speed_revolutions: Option<u16>,
+
+    /// Computed cadence in revolutions per minute
+    pub cadence: Option<f64>,
+    /// Computed speed in km/h
+    pub speed: Option<f64>,
+    /// Whether the wheel, or crank for cadence sensors, has stopped
+    pub stopped: bool,
+
+    // data page 1
+    pub cumulative_operating_time: Option<Duration>,
+
+    // data page 2
+    pub manufacturer_id: Option<u8>,
+    pub serial_number: Option<u16>,
+
+    // data page 3
+    pub hardware_version: Option<u8>,
+    pub software_version: Option<u8>,
+    pub model_number: Option<u8>,
+
+    // data page 4
+    /// Battery voltage in volts
+    pub battery_voltage: Option<f64>,
+    pub battery_status: Option<BatteryStatus>,
+}
+
+impl BikeSpeedCadenceData {
+    fn new(page: Option<u8>) -> BikeSpeedCadenceData {
+        BikeSpeedCadenceData {
+            page,
+
+            cadence_event_time: None,
+            cumulative_cadence_revolutions: None,
+            speed_event_time: None,
+            cumulative_speed_revolutions: None,
+
+            cadence: None,
+            speed: None,
+            stopped: false,
+
+            // data page 1
+            cumulative_operating_time: None,
+
+            // data page 2
+            manufacturer_id: None,
+            serial_number: None,
+
+            // data page 3
+            hardware_version: None,
+            software_version: None,
+            model_number: None,
+
+            // data page 4
+            battery_voltage: None,
+            battery_status: None,
+        }
+    }
+}
+
+pub fn new_search(
+    sensor_type: SensorType,
+) -> (
+    BikeSpeedCadence,
+    crossbeam_channel::Receiver<BikeSpeedCadenceData>,
+) {
+    new_paired(
+        sensor_type,
+        DevicePairing {
+            device_id: 0,
+            transmission_type: 0,
+        },
+    )
+}
+
+pub fn new_paired(
+    sensor_type: SensorType,
+    pairing: DevicePairing,
+) -> (
+    BikeSpeedCadence,
+    crossbeam_channel::Receiver<BikeSpeedCadenceData>,
+) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    let sensor = BikeSpeedCadence {
+        sensor_type,
+        pairing,
+        wheel_circumference: DEFAULT_WHEEL_CIRCUMFERENCE,
+
+        page: None,
+        page_toggle_observed: false,
+        speed: EventTracker::default(),
+        cadence: EventTracker::default(),
+
+        sender,
+    };
+
+    (sensor, receiver)
+}
+
+impl BikeSpeedCadence {
+    /// Set the wheel circumference in millimetres used to compute speed.
+    pub fn set_wheel_circumference(&mut self, circumference: u16) {
+        self.wheel_circumference = circumference;
+    }
+
+    fn update_speed(&mut self, data: &mut BikeSpeedCadenceData, event_time: u16, revs: u16) {
+        let (revolutions_per_second, stopped) = self.speed.update(event_time, revs);
+        data.speed_event_time = Some(event_time);
+        data.cumulative_speed_revolutions = Some(revs);
+        data.speed = revolutions_per_second
+            .map(|rps| rps * f64::from(self.wheel_circumference) / 1000.0 * 3.6);
+        data.stopped = stopped;
+    }
+
+    fn update_cadence(&mut self, data: &mut BikeSpeedCadenceData, event_time: u16, revs: u16) {
+        let (revolutions_per_second, stopped) = self.cadence.update(event_time, revs);
+        data.cadence_event_time = Some(event_time);
+        data.cumulative_cadence_revolutions = Some(revs);
+        data.cadence = revolutions_per_second.map(|rps| rps * 60.0);
+        data.stopped = stopped;
+    }
+
+    fn process_combined(&mut self, data: [u8; 8]) -> BikeSpeedCadenceData {
+        let mut result = BikeSpeedCadenceData::new(None);
+
+        self.update_cadence(
+            &mut result,
+            u16::from_le_bytes([data[0], data[1]]),
+            u16::from_le_bytes([data[2], data[3]]),
+        );
+        // Stopped is reported for the wheel, as riders may freewheel without pedalling
+        self.update_speed(
+            &mut result,
+            u16::from_le_bytes([data[4], data[5]]),
+            u16::from_le_bytes([data[6], data[7]]),
+        );
+
+        result
+    }
+
+    fn process_paged(&mut self, data: [u8; 8]) -> BikeSpeedCadenceData {
+        if !self.page_toggle_observed {
+            if let Some(page) = self.page {
+                if page & 0x80 != data[0] & 0x80 {
+                    self.page_toggle_observed = true;
+                }
+            }
+        }
+        self.page = Some(data[0]);
+
+        let page = data[0] & 0x7f;
+        let mut result = BikeSpeedCadenceData::new(Some(page));
+
+        let event_time = u16::from_le_bytes([data[4], data[5]]);
+        let revolutions = u16::from_le_bytes([data[6], data[7]]);
+        if self.sensor_type == SensorType::Speed {
+            self.update_speed(&mut result, event_time, revolutions);
+        } else {
+            self.update_cadence(&mut result, event_time, revolutions);
+        }
+
+        if self.page_toggle_observed {
+            match page {
+                0 => {}
+                1 => {
+                    let raw = u32::from_le_bytes([data[1], data[2], data[3], 0]);
+                    result.cumulative_operating_time = Some(Duration::from_secs((raw * 2).into()));
+                }
+                2 => {
+                    result.manufacturer_id = Some(data[1]);
+                    result.serial_number = Some(u16::from_le_bytes([data[2], data[3]]));
+                }
+                3 => {
+                    result.hardware_version = Some(data[1]);
+                    result.software_version = Some(data[2]);
+                    result.model_number = Some(data[3]);
+                }
+                4 => {
+                    result.battery_voltage = match data[3] & 0x0f {
+                        0x0f => None,
+                        coarse => Some(f64::from(coarse) + f64::from(data[2]) / 256.0),
+                    };
+                    result.battery_status = ((data[3] >> 4) & 0x07).try_into().ok();
+                }
+                5 if self.sensor_type == SensorType::Speed => {
+                    if data[1] & 0x01 != 0 {
+                        result.stopped = true;
+                        result.speed = Some(0.0);
+                    }
+                }
+                // The measurement is still valid for pages which aren't handled
+                _ => warn!("received unhandled data page: {:?}", data),
+            }
+        }
+
+        result
+    }
+}
+
+impl Device for BikeSpeedCadence {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        self.sensor_type.into()
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        self.sensor_type.channel_period()
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for BikeSpeedCadence {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let result = match self.sensor_type {
+                SensorType::SpeedAndCadence => self.process_combined(data),
+                SensorType::Speed | SensorType::Cadence => self.process_paged(data),
+            };
+
+            self.sender.try_send(result)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::test::payload;
+
+    fn combined_page(cadence: (u16, u16), speed: (u16, u16)) -> [u8; 8] {
+        let [ct0, ct1] = cadence.0.to_le_bytes();
+        let [cr0, cr1] = cadence.1.to_le_bytes();
+        let [st0, st1] = speed.0.to_le_bytes();
+        let [sr0, sr1] = speed.1.to_le_bytes();
+        [ct0, ct1, cr0, cr1, st0, st1, sr0, sr1]
+    }
+
+    #[test]
+    fn it_computes_combined_speed_and_cadence() {
+        let (mut sensor, receiver) = new_search(SensorType::SpeedAndCadence);
+
+        assert_eq!(
+            sensor.process_data(payload(combined_page((1024, 10), (2048, 100)))),
+            Ok(())
+        );
+        let data = receiver.try_recv().unwrap();
+        assert_eq!(data.page, None);
+        assert_eq!(data.cumulative_cadence_revolutions, Some(10));
+        assert_eq!(data.speed_event_time, Some(2048));
+        assert_eq!(data.cadence, None);
+        assert_eq!(data.speed, None);
+
+        // 1.5 crank revolutions per second, and 4 wheel revolutions per second
+        sensor
+            .process_data(payload(combined_page((3072, 13), (3072, 104))))
+            .unwrap();
+        let data = receiver.try_recv().unwrap();
+        assert_eq!(data.cadence, Some(90.0));
+        assert_eq!(data.speed, Some(4.0 * 2.096 * 3.6));
+    }
+
+    #[test]
+    fn it_handles_rollover() {
+        let (mut sensor, receiver) = new_search(SensorType::Speed);
+        sensor.set_wheel_circumference(2000);
+
+        sensor
+            .process_data(payload([0, 0xff, 0xff, 0xff, 0x00, 0xfc, 0xfe, 0xff]))
+            .unwrap();
+        sensor
+            .process_data(payload([0, 0xff, 0xff, 0xff, 0x00, 0x04, 0x02, 0x00]))
+            .unwrap();
+        let data = receiver.try_iter().last().unwrap();
+        // 4 revolutions in 2 seconds
+        assert_eq!(data.cumulative_speed_revolutions, Some(2));
+        assert_eq!(data.speed, Some(2.0 * 2.0 * 3.6));
+    }
+
+    #[test]
+    fn it_detects_stop() {
+        let (mut sensor, receiver) = new_search(SensorType::Cadence);
+
+        sensor
+            .process_data(payload([0, 0xff, 0xff, 0xff, 0, 0, 0, 0]))
+            .unwrap();
+        sensor
+            .process_data(payload([0, 0xff, 0xff, 0xff, 0, 4, 1, 0]))
+            .unwrap();
+        for _ in 0..STOP_MESSAGES {
+            sensor
+                .process_data(payload([0, 0xff, 0xff, 0xff, 0, 4, 1, 0]))
+                .unwrap();
+        }
+
+        let data: Vec<BikeSpeedCadenceData> = receiver.try_iter().collect();
+        assert_eq!(data[1].cadence, Some(60.0));
+        assert!(!data[data.len() - 2].stopped);
+        assert_eq!(data[data.len() - 2].cadence, Some(60.0));
+        assert!(data[data.len() - 1].stopped);
+        assert_eq!(data[data.len() - 1].cadence, Some(0.0));
+    }
+
+    #[test]
+    fn it_processes_stop_indicator() {
+        let (mut sensor, receiver) = new_search(SensorType::Speed);
+
+        sensor
+            .process_data(payload([0, 0xff, 0xff, 0xff, 0, 4, 1, 0]))
+            .unwrap();
+        sensor
+            .process_data(payload([0x85, 0x01, 0xff, 0xff, 0, 4, 1, 0]))
+            .unwrap();
+
+        let data = receiver.try_iter().last().unwrap();
+        assert_eq!(data.page, Some(5));
+        assert!(data.stopped);
+        assert_eq!(data.speed, Some(0.0));
+    }
+
+    #[test]
+    fn it_processes_background_pages_after_page_change_toggle() {
+        let (mut sensor, receiver) = new_search(SensorType::Cadence);
+
+        sensor
+            .process_data(payload([2, 1, 40, 0, 0, 4, 1, 0]))
+            .unwrap();
+        let data = receiver.try_recv().unwrap();
+        assert_eq!(data.page, Some(2));
+        assert_eq!(data.manufacturer_id, None);
+
+        sensor
+            .process_data(payload([0x82, 1, 40, 0, 0, 4, 1, 0]))
+            .unwrap();
+        let data = receiver.try_recv().unwrap();
+        assert_eq!(data.manufacturer_id, Some(1));
+        assert_eq!(data.serial_number, Some(40));
+
+        sensor
+            .process_data(payload([4, 0xff, 0x80, 0x22, 0, 4, 1, 0]))
+            .unwrap();
+        let data = receiver.try_recv().unwrap();
+        assert_eq!(data.battery_voltage, Some(2.5));
+        assert_eq!(data.battery_status, Some(BatteryStatus::Good));
+    }
+
+    #[test]
+    fn it_keeps_measurement_from_unhandled_pages() {
+        let (mut sensor, receiver) = new_search(SensorType::Cadence);
+
+        sensor
+            .process_data(payload([0, 0xff, 0xff, 0xff, 0, 0, 0, 0]))
+            .unwrap();
+        // stop indicator page only sent by speed sensors
+        assert_eq!(
+            sensor.process_data(payload([0x85, 0x01, 0xff, 0xff, 0, 4, 1, 0])),
+            Ok(())
+        );
+
+        let data = receiver.try_iter().last().unwrap();
+        assert_eq!(data.page, Some(5));
+        assert_eq!(data.cumulative_cadence_revolutions, Some(1));
+        assert_eq!(data.cadence, Some(60.0));
+        assert!(!data.stopped);
+    }
+}
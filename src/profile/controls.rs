@@ -0,0 +1,588 @@
+use num_enum::{FromPrimitive, IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error, Transmitter};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    AudioUpdate = 1,
+    ControlDeviceAvailability = 2,
+    AudioCommand = 16,
+    VideoCommand = 17,
+}
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, IntoPrimitive)]
+pub enum GenericCommand {
+    MenuUp = 0,
+    MenuDown = 1,
+    MenuSelect = 2,
+    MenuBack = 3,
+    Home = 4,
+    Start = 32,
+    Stop = 33,
+    Reset = 34,
+    Length = 35,
+    Lap = 36,
+    #[num_enum(catch_all)]
+    Custom(u16),
+}
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, IntoPrimitive)]
+pub enum AudioCommand {
+    Play = 0,
+    Pause = 1,
+    Stop = 2,
+    VolumeUp = 3,
+    VolumeDown = 4,
+    Mute = 5,
+    AheadTrack = 6,
+    BackTrack = 7,
+    RepeatCurrentTrack = 8,
+    RepeatAll = 9,
+    RepeatOff = 10,
+    ShuffleTracks = 11,
+    ShuffleAlbums = 12,
+    ShuffleOff = 13,
+    FastForward = 14,
+    Rewind = 15,
+    #[num_enum(catch_all)]
+    Custom(u16),
+}
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, IntoPrimitive)]
+pub enum VideoCommand {
+    Play = 0,
+    Pause = 1,
+    Stop = 2,
+    VolumeUp = 3,
+    VolumeDown = 4,
+    Mute = 5,
+    Ahead = 6,
+    Back = 7,
+    Record = 8,
+    #[num_enum(catch_all)]
+    Custom(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Generic(GenericCommand),
+    Audio(AudioCommand),
+    Video(VideoCommand),
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum AudioState {
+    Off = 0,
+    Play = 1,
+    Pause = 2,
+    Stop = 3,
+    Busy = 4,
+    Unknown = 15,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum RepeatState {
+    Off = 0,
+    CurrentTrack = 1,
+    All = 2,
+    Custom = 3,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum ShuffleState {
+    Off = 0,
+    Tracks = 1,
+    Albums = 2,
+    Custom = 3,
+}
+
+/// Identifies the remote sending a command, so that controllable devices can tell remotes apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RemoteID {
+    pub serial_number: u16,
+    pub manufacturer_id: u16,
+}
+
+/// Create a command message, sent by a remote to a controllable device.
+///
+/// The sequence number should be incremented for each new command, so that retried messages are
+/// only acted upon once.
+pub fn command_message(
+    channel: u8,
+    remote: RemoteID,
+    sequence: u8,
+    command: Command,
+) -> message::Message {
+    let (page, command): (u8, u16) = match command {
+        Command::Generic(command) => (
+            message::common::PageNumber::GenericCommand.into(),
+            command.into(),
+        ),
+        Command::Audio(command) => (PageNumber::AudioCommand.into(), command.into()),
+        Command::Video(command) => (PageNumber::VideoCommand.into(), command.into()),
+    };
+    let [serial_lsb, serial_msb] = remote.serial_number.to_le_bytes();
+    let [manufacturer_lsb, manufacturer_msb] = remote.manufacturer_id.to_le_bytes();
+    let [command_lsb, command_msb] = command.to_le_bytes();
+
+    message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([
+            page,
+            serial_lsb,
+            serial_msb,
+            manufacturer_lsb,
+            manufacturer_msb,
+            sequence,
+            command_lsb,
+            command_msb,
+        ]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capabilities {
+    pub audio: bool,
+    pub video: bool,
+    pub generic: bool,
+}
+
+impl From<u8> for Capabilities {
+    fn from(value: u8) -> Self {
+        Capabilities {
+            audio: value & 0x01 != 0,
+            video: value & 0x02 != 0,
+            generic: value & 0x10 != 0,
+        }
+    }
+}
+
+impl From<Capabilities> for u8 {
+    fn from(value: Capabilities) -> Self {
+        u8::from(value.audio) | u8::from(value.video) << 1 | u8::from(value.generic) << 4
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioUpdate {
+    /// Percentage
+    pub volume: Option<u8>,
+    /// measured in seconds
+    pub total_track_time: Option<u16>,
+    /// measured in seconds
+    pub current_track_time: Option<u16>,
+    pub state: AudioState,
+    pub repeat: RepeatState,
+    pub shuffle: ShuffleState,
+}
+
+impl AudioUpdate {
+    fn encode(&self) -> [u8; 8] {
+        let [total_lsb, total_msb] = self.total_track_time.unwrap_or(0xffff).to_le_bytes();
+        let [current_lsb, current_msb] = self.current_track_time.unwrap_or(0xffff).to_le_bytes();
+
+        [
+            PageNumber::AudioUpdate.into(),
+            self.volume.unwrap_or(0xff),
+            total_lsb,
+            total_msb,
+            current_lsb,
+            current_msb,
+            0xff,
+            u8::from(self.state) | u8::from(self.repeat) << 4 | u8::from(self.shuffle) << 6,
+        ]
+    }
+
+    fn decode(data: [u8; 8]) -> Result<AudioUpdate, Error> {
+        let time = |lsb, msb| match u16::from_le_bytes([lsb, msb]) {
+            0xffff => None,
+            time => Some(time),
+        };
+
+        Ok(AudioUpdate {
+            volume: match data[1] {
+                0xff => None,
+                volume => Some(volume),
+            },
+            total_track_time: time(data[2], data[3]),
+            current_track_time: time(data[4], data[5]),
+            state: (data[7] & 0x0f).try_into()?,
+            repeat: ((data[7] >> 4) & 0x03).try_into()?,
+            shuffle: (data[7] >> 6).try_into()?,
+        })
+    }
+}
+
+/// Remote control, receiving the state of a controllable device and sending it commands with
+/// `command_message`.
+#[derive(Clone, Debug)]
+pub struct Controls {
+    pairing: DevicePairing,
+
+    sender: crossbeam_channel::Sender<ControlsData>,
+}
+
+pub fn new_search() -> (Controls, crossbeam_channel::Receiver<ControlsData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(pairing: DevicePairing) -> (Controls, crossbeam_channel::Receiver<ControlsData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    (Controls { pairing, sender }, receiver)
+}
+
+impl Device for Controls {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        16
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8192
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for Controls {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::AudioUpdate) => {
+                    ControlsData::AudioUpdate(AudioUpdate::decode(data)?)
+                }
+                Ok(PageNumber::ControlDeviceAvailability) => {
+                    ControlsData::ControlDeviceAvailability(data[7].into())
+                }
+                Ok(PageNumber::AudioCommand | PageNumber::VideoCommand) => {
+                    // Commands sent by another remote
+                    return Ok(());
+                }
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => ControlsData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlsData {
+    AudioUpdate(AudioUpdate),
+    ControlDeviceAvailability(Capabilities),
+    Common(message::common::DataPage),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RemoteCommand {
+    pub remote: RemoteID,
+    pub sequence: u8,
+    pub command: Command,
+}
+
+/// Controllable device, e.g. an application driven by a handlebar remote, assigned with
+/// `Node::assign_transmitter`.
+///
+/// Broadcasts its capabilities, and the audio state when audio is supported. Commands received
+/// from remotes are delivered through the receiver returned by `new_controllable`.
+pub struct ControllableDevice {
+    capabilities: Capabilities,
+    pages_sent: u32,
+    last_command: Option<(RemoteID, u8)>,
+
+    audio: AudioUpdate,
+    audio_updates: crossbeam_channel::Receiver<AudioUpdate>,
+    audio_updater: crossbeam_channel::Sender<AudioUpdate>,
+
+    sender: crossbeam_channel::Sender<RemoteCommand>,
+}
+
+pub fn new_controllable(
+    capabilities: Capabilities,
+) -> (
+    ControllableDevice,
+    crossbeam_channel::Receiver<RemoteCommand>,
+) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (audio_updater, audio_updates) = crossbeam_channel::unbounded();
+
+    let device = ControllableDevice {
+        capabilities,
+        pages_sent: 0,
+        last_command: None,
+
+        audio: AudioUpdate {
+            volume: None,
+            total_track_time: None,
+            current_track_time: None,
+            state: AudioState::Unknown,
+            repeat: RepeatState::Off,
+            shuffle: ShuffleState::Off,
+        },
+        audio_updates,
+        audio_updater,
+
+        sender,
+    };
+
+    (device, receiver)
+}
+
+impl ControllableDevice {
+    /// Sender for updating the broadcast audio state, which can be used after the device has been
+    /// assigned to a channel.
+    pub fn audio_updater(&self) -> crossbeam_channel::Sender<AudioUpdate> {
+        self.audio_updater.clone()
+    }
+}
+
+impl Transmitter for ControllableDevice {
+    fn device_type(&self) -> u8 {
+        16
+    }
+
+    fn transmission_type(&self) -> u8 {
+        5
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8192
+    }
+
+    fn next_page(&mut self) -> [u8; 8] {
+        if let Some(audio) = self.audio_updates.try_iter().last() {
+            self.audio = audio;
+        }
+
+        // Audio devices alternate between audio state and availability
+        let page = if self.capabilities.audio && self.pages_sent.is_multiple_of(2) {
+            self.audio.encode()
+        } else {
+            [
+                PageNumber::ControlDeviceAvailability.into(),
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                self.capabilities.into(),
+            ]
+        };
+
+        self.pages_sent += 1;
+        page
+    }
+}
+
+impl DataProcessor for ControllableDevice {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        let Some(data) = data.data else {
+            return Ok(());
+        };
+
+        // Audio and video commands share the layout of the common generic command page
+        let (serial_number, manufacturer_id, sequence, command) = match data[0].try_into() {
+            Ok(page @ (PageNumber::AudioCommand | PageNumber::VideoCommand)) => {
+                let command = u16::from_le_bytes([data[6], data[7]]);
+                (
+                    u16::from_le_bytes([data[1], data[2]]),
+                    u16::from_le_bytes([data[3], data[4]]),
+                    data[5],
+                    match page {
+                        PageNumber::AudioCommand => Command::Audio(command.into()),
+                        _ => Command::Video(command.into()),
+                    },
+                )
+            }
+            _ => match message::common::decode(data) {
+                Some(message::common::DataPage::GenericCommand {
+                    serial_number,
+                    manufacturer_id,
+                    sequence_no,
+                    command,
+                }) => (
+                    serial_number,
+                    manufacturer_id,
+                    sequence_no,
+                    Command::Generic(command.into()),
+                ),
+                _ => {
+                    warn!("received unhandled data page: {:?}", data);
+                    return Ok(());
+                }
+            },
+        };
+        let remote = RemoteID {
+            serial_number,
+            manufacturer_id,
+        };
+
+        // Retried acknowledged messages repeat the sequence number
+        if self.last_command == Some((remote, sequence)) {
+            return Ok(());
+        }
+        self.last_command = Some((remote, sequence));
+
+        self.sender.try_send(RemoteCommand {
+            remote,
+            sequence,
+            command,
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::test::payload;
+
+    fn command_payload(message: message::Message) -> message::DataPayload {
+        let message::Message::AcknowledgedData(payload) = message else {
+            panic!("expected acknowledged data");
+        };
+        payload
+    }
+
+    const REMOTE: RemoteID = RemoteID {
+        serial_number: 1234,
+        manufacturer_id: 1,
+    };
+
+    #[test]
+    fn it_creates_generic_command_message() {
+        assert_eq!(
+            command_message(2, REMOTE, 7, Command::Generic(GenericCommand::Lap)),
+            message::Message::AcknowledgedData(message::DataPayload {
+                channel: 2,
+                data: Some([73, 0xd2, 0x04, 0x01, 0x00, 7, 36, 0]),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_delivers_commands_to_controllable_device() {
+        let (mut device, receiver) = new_controllable(Capabilities {
+            audio: true,
+            video: false,
+            generic: true,
+        });
+
+        let commands = [
+            command_message(0, REMOTE, 1, Command::Generic(GenericCommand::Start)),
+            // retried message
+            command_message(0, REMOTE, 1, Command::Generic(GenericCommand::Start)),
+            command_message(0, REMOTE, 2, Command::Audio(AudioCommand::VolumeUp)),
+            command_message(0, REMOTE, 3, Command::Video(VideoCommand::Custom(0x8000))),
+        ];
+        for command in commands {
+            assert_eq!(device.process_data(command_payload(command)), Ok(()));
+        }
+
+        let commands: Vec<Command> = receiver.try_iter().map(|c| c.command).collect();
+        assert_eq!(
+            commands,
+            vec![
+                Command::Generic(GenericCommand::Start),
+                Command::Audio(AudioCommand::VolumeUp),
+                Command::Video(VideoCommand::Custom(0x8000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_broadcasts_controllable_device_state_to_remote() {
+        let (mut device, _commands) = new_controllable(Capabilities {
+            audio: true,
+            video: false,
+            generic: true,
+        });
+        let audio = AudioUpdate {
+            volume: Some(40),
+            total_track_time: Some(215),
+            current_track_time: Some(12),
+            state: AudioState::Play,
+            repeat: RepeatState::All,
+            shuffle: ShuffleState::Off,
+        };
+        device.audio_updater().send(audio).unwrap();
+
+        let (mut remote, receiver) = new_search();
+        for _ in 0..2 {
+            assert_eq!(remote.process_data(payload(device.next_page())), Ok(()));
+        }
+
+        let data: Vec<ControlsData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                ControlsData::AudioUpdate(audio),
+                ControlsData::ControlDeviceAvailability(Capabilities {
+                    audio: true,
+                    video: false,
+                    generic: true,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_broadcasts_availability_only_without_audio() {
+        let (mut device, _commands) = new_controllable(Capabilities {
+            audio: false,
+            video: false,
+            generic: true,
+        });
+
+        for _ in 0..2 {
+            assert_eq!(
+                device.next_page(),
+                [2, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x10]
+            );
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    GeneralInformation = 0,
+    Temperature = 1,
+}
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum EnvironmentPeriod {
+    Period4Hz = 8192,
+    /// Low power rate, at which only the temperature page is broadcast. Other pages must be
+    /// requested, e.g. with `general_information_request_message`.
+    Period0_5Hz = 65535,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum TimeSupport {
+    NotSupported = 0,
+    NotSet = 1,
+    Set = 2,
+}
+
+#[derive(Clone, Debug)]
+pub struct Environment {
+    pairing: DevicePairing,
+    period: EnvironmentPeriod,
+
+    sender: crossbeam_channel::Sender<EnvironmentData>,
+}
+
+pub fn new_search() -> (Environment, crossbeam_channel::Receiver<EnvironmentData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(
+    pairing: DevicePairing,
+) -> (Environment, crossbeam_channel::Receiver<EnvironmentData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    let environment = Environment {
+        pairing,
+        period: EnvironmentPeriod::Period4Hz,
+
+        sender,
+    };
+
+    (environment, receiver)
+}
+
+impl Environment {
+    pub fn set_channel_period(&mut self, period: u16) -> Result<(), Error> {
+        match period.try_into() {
+            Ok(period) => {
+                self.period = period;
+                Ok(())
+            }
+            Err(_) => Err(Error::InvalidValue),
+        }
+    }
+}
+
+/// Create a request for the general information page, which is not broadcast at 0.5Hz.
+pub fn general_information_request_message(channel: u8) -> message::Message {
+    message::request_data_page(channel, PageNumber::GeneralInformation.into())
+}
+
+/// Decode a 12 bit signed temperature in 0.1°C, where 0x800 is invalid.
+fn temperature_12bit(value: u16) -> Option<f64> {
+    match value {
+        0x800 => None,
+        value => Some(f64::from(((value << 4) as i16) >> 4) / 10.0),
+    }
+}
+
+impl Device for Environment {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        25
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        self.period.into()
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for Environment {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::GeneralInformation) => EnvironmentData::GeneralInformation {
+                    local_time: (data[3] & 0x03).try_into()?,
+                    utc_time: ((data[3] >> 2) & 0x03).try_into()?,
+                    default_period: if data[3] & 0x10 != 0 {
+                        EnvironmentPeriod::Period4Hz
+                    } else {
+                        EnvironmentPeriod::Period0_5Hz
+                    },
+                    supported_pages: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+                },
+                Ok(PageNumber::Temperature) => EnvironmentData::Temperature {
+                    event_count: data[2],
+                    low: temperature_12bit(u16::from(data[3]) | u16::from(data[4] & 0x0f) << 8),
+                    high: temperature_12bit(u16::from(data[4] >> 4) | u16::from(data[5]) << 4),
+                    current: match i16::from_le_bytes([data[6], data[7]]) {
+                        i16::MIN => None,
+                        current => Some(f64::from(current) / 100.0),
+                    },
+                },
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => EnvironmentData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnvironmentData {
+    GeneralInformation {
+        local_time: TimeSupport,
+        utc_time: TimeSupport,
+        default_period: EnvironmentPeriod,
+        /// Bit field of supported pages, bit 0 being page 0
+        supported_pages: u32,
+    },
+    /// Temperatures in °C
+    Temperature {
+        event_count: u8,
+        /// Lowest temperature in the last 24 hours
+        low: Option<f64>,
+        /// Highest temperature in the last 24 hours
+        high: Option<f64>,
+        current: Option<f64>,
+    },
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn process(environment: &mut Environment, data: [u8; 8]) {
+        assert_eq!(
+            environment.process_data(message::DataPayload {
+                channel: 0,
+                data: Some(data),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn it_processes_temperature_page() {
+        let (mut environment, receiver) = new_search();
+        // low of -2.5°C (0xfe7), high of 25.6°C (0x100), current of 21.37°C
+        process(&mut environment, [1, 0xff, 7, 0xe7, 0x0f, 0x10, 0x59, 0x08]);
+        process(&mut environment, [1, 0xff, 8, 0x00, 0x08, 0x80, 0x00, 0x80]);
+
+        let data: Vec<EnvironmentData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                EnvironmentData::Temperature {
+                    event_count: 7,
+                    low: Some(-2.5),
+                    high: Some(25.6),
+                    current: Some(21.37),
+                },
+                EnvironmentData::Temperature {
+                    event_count: 8,
+                    low: None,
+                    high: None,
+                    current: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_processes_general_information_page() {
+        let (mut environment, receiver) = new_search();
+        process(
+            &mut environment,
+            [0, 0xff, 0xff, 0b0000_0001, 0x03, 0, 0, 0],
+        );
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            EnvironmentData::GeneralInformation {
+                local_time: TimeSupport::NotSet,
+                utc_time: TimeSupport::NotSupported,
+                default_period: EnvironmentPeriod::Period0_5Hz,
+                supported_pages: 0x03,
+            }
+        );
+    }
+
+    #[test]
+    fn it_uses_low_power_channel_period() {
+        let (mut environment, _receiver) = new_search();
+        assert_eq!(environment.channel_period(), 8192);
+        assert_eq!(environment.set_channel_period(65535), Ok(()));
+        assert_eq!(environment.channel_period(), 65535);
+        assert_eq!(
+            environment.set_channel_period(4096),
+            Err(Error::InvalidValue)
+        );
+
+        assert_eq!(
+            general_information_request_message(1),
+            message::request_data_page(1, 0)
+        );
+    }
+}
@@ -1,3 +1,5 @@
+pub mod simulator;
+
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 use crate::device::{DataProcessor, Device, DevicePairing, Error};
@@ -0,0 +1,595 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use super::{Command, EquipmentState, EquipmentType};
+use crate::device::{DataProcessor, Error, Transmitter};
+use crate::message::{self, common::PageNumber, CommandStatus};
+
+const GRAVITY: f64 = 9.81;
+/// Ratio of wheel to crank revolutions, as if riding a 50x20 gear.
+const GEAR_RATIO: f64 = 2.5;
+
+const BASIC_RESISTANCE_PAGE: u8 = 48;
+const USER_CONFIGURATION_PAGE: u8 = 55;
+const REQUEST_DATA_PAGE: u8 = 70;
+const CAPABILITIES_PAGE: u8 = 54;
+
+/// Pages broadcast in turn, with common pages 80 and 81 sent after every 64 pages.
+const PAGE_PATTERN: [u8; 8] = [16, 16, 25, 25, 16, 16, 26, 26];
+const BACKGROUND_INTERVAL: u32 = 64;
+
+/// How the simulated trainer sets its resistance, following the last control page received.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlMode {
+    /// Resistance as a fraction of the trainer's maximum resistance, in 0.5% increments
+    BasicResistance(u8),
+    /// Target power in 0.25W increments, regardless of cadence
+    TargetPower(u16),
+    /// Resistance simulating riding on a road with the configured track and wind resistance
+    Simulation,
+}
+
+/// Simulated stationary bike trainer, transmitting pages that can be decoded by
+/// [`super::FitnessEquipment`].
+///
+/// The trainer is controlled by the acknowledged control pages created by this module's message
+/// functions, and answers data page requests for command status (page 71) and capabilities
+/// (page 54). Power is calculated from a simple physics model: the rider pedals at a constant
+/// cadence set with [`FitnessEquipmentSimulator::set_cadence`] in a fixed gear, and the trainer
+/// resists according to its control mode.
+pub struct FitnessEquipmentSimulator {
+    cadence: u8,
+    mode: ControlMode,
+    /// Maximum braking force in newtons
+    maximum_resistance: u16,
+
+    // simulation parameters
+    user_weight: f64,
+    bike_weight: f64,
+    wheel_diameter: f64,
+    grade: f64,
+    rolling_resistance_coefficient: f64,
+    wind_resistance_coefficient: f64,
+    wind_speed: f64,
+    drafting_factor: f64,
+
+    // last command, reported by page 71
+    last_command: Option<(u8, [u8; 4])>,
+    sequence_no: u8,
+    requested_pages: VecDeque<u8>,
+
+    // accumulated state
+    pages_sent: u32,
+    time: f64,
+    distance: f64,
+    power: u16,
+    accumulated_power: u16,
+    power_event_count: u8,
+    torque_event_count: u8,
+    wheel_revolutions: f64,
+    wheel_period: f64,
+    accumulated_torque: f64,
+}
+
+impl Default for FitnessEquipmentSimulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FitnessEquipmentSimulator {
+    /// Create a trainer with the default simulation parameters of the FE-C control pages, and a
+    /// rider not pedalling.
+    pub fn new() -> FitnessEquipmentSimulator {
+        FitnessEquipmentSimulator {
+            cadence: 0,
+            mode: ControlMode::Simulation,
+            maximum_resistance: 1000,
+
+            user_weight: 75.0,
+            bike_weight: 10.0,
+            wheel_diameter: 0.7,
+            grade: 0.0,
+            rolling_resistance_coefficient: 0.004,
+            wind_resistance_coefficient: 0.51,
+            wind_speed: 0.0,
+            drafting_factor: 1.0,
+
+            last_command: None,
+            sequence_no: 0xff,
+            requested_pages: VecDeque::new(),
+
+            pages_sent: 0,
+            time: 0.0,
+            distance: 0.0,
+            power: 0,
+            accumulated_power: 0,
+            power_event_count: 0,
+            torque_event_count: 0,
+            wheel_revolutions: 0.0,
+            wheel_period: 0.0,
+            accumulated_torque: 0.0,
+        }
+    }
+
+    /// Set the cadence the simulated rider pedals at, in revolutions per minute.
+    pub fn set_cadence(&mut self, cadence: u8) {
+        self.cadence = cadence;
+    }
+
+    pub fn mode(&self) -> ControlMode {
+        self.mode
+    }
+
+    /// Power in watts the trainer reported in its last page.
+    pub fn power(&self) -> u16 {
+        self.power
+    }
+
+    /// Speed in m/s of the simulated rider.
+    fn speed(&self) -> f64 {
+        f64::from(self.cadence) / 60.0 * GEAR_RATIO * PI * self.wheel_diameter
+    }
+
+    fn state(&self) -> EquipmentState {
+        if self.cadence > 0 {
+            EquipmentState::InUse
+        } else {
+            EquipmentState::Ready
+        }
+    }
+
+    fn calculate_power(&self, speed: f64) -> f64 {
+        match self.mode {
+            ControlMode::BasicResistance(resistance) => {
+                f64::from(resistance) / 200.0 * f64::from(self.maximum_resistance) * speed
+            }
+            ControlMode::TargetPower(_) if self.cadence == 0 => 0.0,
+            ControlMode::TargetPower(power) => f64::from(power) / 4.0,
+            ControlMode::Simulation => {
+                let mass = self.user_weight + self.bike_weight;
+                let gradient = mass * GRAVITY * self.grade.atan().sin();
+                let rolling =
+                    mass * GRAVITY * self.grade.atan().cos() * self.rolling_resistance_coefficient;
+                let air_speed = speed + self.wind_speed;
+                let aerodynamic = 0.5
+                    * self.wind_resistance_coefficient
+                    * air_speed
+                    * air_speed.abs()
+                    * self.drafting_factor;
+                ((gradient + rolling + aerodynamic) * speed).max(0.0)
+            }
+        }
+    }
+
+    /// Advance the simulation by one channel period.
+    fn advance(&mut self) {
+        let dt = f64::from(self.channel_period()) / 32768.0;
+        let speed = self.speed();
+        let power = self.calculate_power(speed);
+
+        self.time += dt;
+        self.distance += speed * dt;
+        self.power = (power.round() as u16).min(0xffe);
+
+        let wheel_circumference = PI * self.wheel_diameter;
+        self.wheel_revolutions += speed * dt / wheel_circumference;
+        self.wheel_period += dt * 2048.0;
+        if speed > 0.0 {
+            // Torque at the wheel in 1/32Nm, from power and the wheel's angular velocity
+            let angular_velocity = speed / (self.wheel_diameter / 2.0);
+            self.accumulated_torque += power / angular_velocity * 32.0 * dt;
+        }
+    }
+
+    fn page_number(&mut self) -> u8 {
+        if let Some(page) = self.requested_pages.pop_front() {
+            return page;
+        }
+
+        let cycle = self.pages_sent % (BACKGROUND_INTERVAL + 2);
+        if cycle == BACKGROUND_INTERVAL {
+            PageNumber::ManufacturerInformation.into()
+        } else if cycle == BACKGROUND_INTERVAL + 1 {
+            PageNumber::ProductInformation.into()
+        } else {
+            PAGE_PATTERN[cycle as usize % PAGE_PATTERN.len()]
+        }
+    }
+
+    fn general_page(&self) -> [u8; 8] {
+        let [speed_lsb, speed_msb] = ((self.speed() * 1000.0).round() as u16).to_le_bytes();
+        let state: u8 = self.state() as u8;
+        [
+            16,
+            EquipmentType::StationaryBike as u8,
+            ((self.time * 4.0) as u64 % 256) as u8,
+            (self.distance as u64 % 256) as u8,
+            speed_lsb,
+            speed_msb,
+            0xff,
+            // distance traveled enabled, no heart rate source
+            (1 << 2) | (state << 4),
+        ]
+    }
+
+    fn stationary_bike_page(&mut self) -> [u8; 8] {
+        self.power_event_count = self.power_event_count.wrapping_add(1);
+        self.accumulated_power = self.accumulated_power.wrapping_add(self.power);
+
+        // Target power status is only reported as speed too low, when the rider isn't pedalling
+        let target_power_status: u8 = match self.mode {
+            ControlMode::TargetPower(_) if self.cadence == 0 => 1,
+            _ => 0,
+        };
+
+        let [accumulated_lsb, accumulated_msb] = self.accumulated_power.to_le_bytes();
+        let [power_lsb, power_msb] = self.power.to_le_bytes();
+        let state: u8 = self.state() as u8;
+        [
+            25,
+            self.power_event_count,
+            self.cadence,
+            accumulated_lsb,
+            accumulated_msb,
+            power_lsb,
+            power_msb & 0x0f,
+            target_power_status | (state << 4),
+        ]
+    }
+
+    fn torque_page(&mut self) -> [u8; 8] {
+        self.torque_event_count = self.torque_event_count.wrapping_add(1);
+
+        let [period_lsb, period_msb] = ((self.wheel_period as u64 % 0x10000) as u16).to_le_bytes();
+        let [torque_lsb, torque_msb] =
+            ((self.accumulated_torque as u64 % 0x10000) as u16).to_le_bytes();
+        let state: u8 = self.state() as u8;
+        [
+            26,
+            self.torque_event_count,
+            (self.wheel_revolutions as u64 % 256) as u8,
+            period_lsb,
+            period_msb,
+            torque_lsb,
+            torque_msb,
+            state << 4,
+        ]
+    }
+
+    fn capabilities_page(&self) -> [u8; 8] {
+        let [resistance_lsb, resistance_msb] = self.maximum_resistance.to_le_bytes();
+        [
+            CAPABILITIES_PAGE,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            resistance_lsb,
+            resistance_msb,
+            // basic resistance, target power and simulation modes
+            0x07,
+        ]
+    }
+
+    fn command_status_page(&self) -> [u8; 8] {
+        let (command_id, command_status, response) = match self.last_command {
+            Some((command_id, response)) => (command_id, CommandStatus::Pass, response),
+            None => (0xff, CommandStatus::Uninitialized, [0xff; 4]),
+        };
+        [
+            PageNumber::CommandStatus.into(),
+            command_id,
+            self.sequence_no,
+            command_status.into(),
+            response[0],
+            response[1],
+            response[2],
+            response[3],
+        ]
+    }
+
+    fn record_command(&mut self, command_id: u8, response: [u8; 4]) {
+        self.last_command = Some((command_id, response));
+        self.sequence_no = self.sequence_no.wrapping_add(1);
+    }
+}
+
+impl Transmitter for FitnessEquipmentSimulator {
+    fn device_type(&self) -> u8 {
+        17
+    }
+
+    fn transmission_type(&self) -> u8 {
+        5
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8192
+    }
+
+    fn next_page(&mut self) -> [u8; 8] {
+        self.advance();
+
+        let page = match self.page_number() {
+            16 => self.general_page(),
+            25 => self.stationary_bike_page(),
+            26 => self.torque_page(),
+            CAPABILITIES_PAGE => self.capabilities_page(),
+            71 => self.command_status_page(),
+            80 => [80, 0xff, 0xff, 1, 0xff, 0x00, 1, 0],
+            _ => [81, 0xff, 0xff, 1, 1, 0, 0, 0],
+        };
+
+        self.pages_sent += 1;
+        page
+    }
+}
+
+impl DataProcessor for FitnessEquipmentSimulator {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        let Some(data) = data.data else {
+            return Ok(());
+        };
+
+        match data[0] {
+            BASIC_RESISTANCE_PAGE => {
+                self.mode = ControlMode::BasicResistance(data[7].min(200));
+                self.record_command(data[0], [0xff, 0xff, 0xff, data[7]]);
+            }
+            page if page == Command::TargetPower.into() => {
+                let power = u16::from_le_bytes([data[6], data[7]]);
+                self.mode = ControlMode::TargetPower(power);
+                self.record_command(page, [0xff, 0xff, data[6], data[7]]);
+            }
+            page if page == Command::WindResistance.into() => {
+                self.wind_resistance_coefficient = match data[5] {
+                    0xff => 0.51,
+                    coefficient => f64::from(coefficient) / 100.0,
+                };
+                self.wind_speed = match data[6] {
+                    0xff => 0.0,
+                    speed => (f64::from(speed) - 127.0) / 3.6,
+                };
+                self.drafting_factor = match data[7] {
+                    0xff => 1.0,
+                    factor => f64::from(factor.min(100)) / 100.0,
+                };
+                self.mode = ControlMode::Simulation;
+                self.record_command(page, [0xff, data[5], data[6], data[7]]);
+            }
+            page if page == Command::TrackResistance.into() => {
+                self.grade = match u16::from_le_bytes([data[5], data[6]]) {
+                    0xffff => 0.0,
+                    grade => (f64::from(grade) / 100.0 - 200.0) / 100.0,
+                };
+                self.rolling_resistance_coefficient = match data[7] {
+                    0xff => 0.004,
+                    coefficient => f64::from(coefficient) * 5e-5,
+                };
+                self.mode = ControlMode::Simulation;
+                self.record_command(page, [0xff, data[5], data[6], data[7]]);
+            }
+            USER_CONFIGURATION_PAGE => {
+                if let weight @ 0..=0xfffe = u16::from_le_bytes([data[1], data[2]]) {
+                    self.user_weight = f64::from(weight) / 100.0;
+                }
+                let bike_weight = u16::from(data[4] >> 4) | (u16::from(data[5]) << 4);
+                if bike_weight != 0xfff {
+                    self.bike_weight = f64::from(bike_weight) * 0.05;
+                }
+                if data[6] != 0xff {
+                    self.wheel_diameter = f64::from(data[6]) / 100.0;
+                }
+            }
+            REQUEST_DATA_PAGE => {
+                let page = data[6];
+                if page == CAPABILITIES_PAGE || page == u8::from(PageNumber::CommandStatus) {
+                    // Bits 0-6 are the number of times to send the page, where 0 is invalid
+                    let times = (data[5] & 0x7f).max(1);
+                    for _ in 0..times {
+                        self.requested_pages.push_back(page);
+                    }
+                }
+            }
+            _ => return Err(Error::InvalidValue),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::device::DevicePairing;
+    use crate::node::{emulator::Emulator, NodeBuilder};
+    use crate::profile::fitness_equipment::{
+        self, new_paired, CapabilitiesData, CommandStatusData, FitnessEquipmentData,
+        TargetPowerStatus,
+    };
+    use crate::profile::test::payload;
+
+    const DEVICE_NUMBER: u16 = 12345;
+
+    fn command(message: message::Message) -> message::DataPayload {
+        match message {
+            message::Message::AcknowledgedData(data) => data,
+            message => panic!("unexpected message: {}", message),
+        }
+    }
+
+    fn decode_pages(
+        simulator: &mut FitnessEquipmentSimulator,
+        pages: usize,
+    ) -> Vec<FitnessEquipmentData> {
+        let (mut fe, receiver) = new_paired(DevicePairing {
+            device_id: DEVICE_NUMBER,
+            transmission_type: 5,
+        });
+        for _ in 0..pages {
+            fe.process_data(payload(simulator.next_page())).unwrap();
+        }
+        receiver.try_iter().collect()
+    }
+
+    fn last_power(data: &[FitnessEquipmentData]) -> Option<u16> {
+        data.iter().rev().find_map(|data| match data {
+            FitnessEquipmentData::StationaryBike(data) => data.instantaneous_power,
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn it_broadcasts_decodable_pages() {
+        let mut simulator = FitnessEquipmentSimulator::new();
+        simulator.set_cadence(90);
+        let data = decode_pages(&mut simulator, 66);
+
+        assert!(data.iter().any(|d| matches!(
+            d,
+            FitnessEquipmentData::General(general)
+                if general.equipment_type == EquipmentType::StationaryBike
+                    && general.state == EquipmentState::InUse
+                    && general.speed == Some(8247)
+        )));
+        assert!(data.iter().any(|d| matches!(
+            d,
+            FitnessEquipmentData::StationaryBike(bike) if bike.cadence == Some(90)
+        )));
+        assert!(data.iter().any(|d| matches!(
+            d,
+            FitnessEquipmentData::StationaryBikeTorque(torque) if torque.wheel_revolutions > 0
+        )));
+        assert!(data
+            .iter()
+            .any(|d| matches!(d, FitnessEquipmentData::Common(_))));
+    }
+
+    #[test]
+    fn it_follows_control_pages() {
+        let mut simulator = FitnessEquipmentSimulator::new();
+        simulator.set_cadence(90);
+
+        // Default simulation of flat road with no wind
+        let flat = last_power(&decode_pages(&mut simulator, 8)).unwrap();
+        assert!((120..180).contains(&flat), "flat power: {}", flat);
+
+        simulator
+            .process_data(command(fitness_equipment::track_resistance_message(
+                0, 20500, 0xff,
+            )))
+            .unwrap();
+        assert_eq!(simulator.mode(), ControlMode::Simulation);
+        let climbing = last_power(&decode_pages(&mut simulator, 8)).unwrap();
+        assert!(climbing > flat + 300, "climbing power: {}", climbing);
+
+        simulator
+            .process_data(command(fitness_equipment::target_power_message(0, 800)))
+            .unwrap();
+        assert_eq!(simulator.mode(), ControlMode::TargetPower(800));
+        assert_eq!(last_power(&decode_pages(&mut simulator, 8)), Some(200));
+
+        simulator.set_cadence(0);
+        let data = decode_pages(&mut simulator, 8);
+        assert_eq!(last_power(&data), Some(0));
+        assert!(data.iter().any(|d| matches!(
+            d,
+            FitnessEquipmentData::StationaryBike(bike)
+                if bike.target_power_status == TargetPowerStatus::SpeedTooLow
+        )));
+    }
+
+    #[test]
+    fn it_answers_data_page_requests() {
+        let mut simulator = FitnessEquipmentSimulator::new();
+
+        simulator
+            .process_data(command(message::request_data_page(0, 54)))
+            .unwrap();
+        assert_eq!(
+            decode_pages(&mut simulator, 1),
+            vec![FitnessEquipmentData::Capabilities(CapabilitiesData {
+                maximum_resistance: Some(1000),
+                basic_resistance: true,
+                target_power: true,
+                simulation: true,
+            })]
+        );
+
+        simulator
+            .process_data(command(fitness_equipment::wind_resistance_message(
+                0, 40, 127, 100,
+            )))
+            .unwrap();
+        simulator
+            .process_data(command(message::request_data_page(0, 71)))
+            .unwrap();
+        assert_eq!(
+            decode_pages(&mut simulator, 1),
+            vec![FitnessEquipmentData::CommandStatus(CommandStatusData {
+                command_id: Command::WindResistance.into(),
+                sequence_no: 0,
+                command_status: CommandStatus::Pass,
+                total_resistance: None,
+                target_power: None,
+                wind_resistance_coefficient: Some(40),
+                wind_speed: Some(127),
+                drafting_factor: Some(100),
+                grade: None,
+                rolling_resistance_coefficient: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn it_stands_in_for_trainer_on_node() {
+        let emulator = Emulator::new();
+        let mut node = NodeBuilder::new([1, 2, 3, 4, 5, 6, 7, 8])
+            .transport(emulator.clone())
+            .device_number(DEVICE_NUMBER)
+            .build();
+        node.open().unwrap();
+
+        let mut simulator = FitnessEquipmentSimulator::new();
+        simulator.set_cadence(90);
+        node.assign_transmitter(Box::new(simulator)).unwrap();
+
+        let (fe, receiver) = new_paired(DevicePairing {
+            device_id: DEVICE_NUMBER,
+            transmission_type: 5,
+        });
+        let channel = node.assign_channel(Box::new(fe), None).unwrap();
+
+        let target_power = command(fitness_equipment::target_power_message(channel, 1000));
+        node.send_acknowledged(channel, target_power.data.unwrap())
+            .unwrap();
+        let request = command(message::request_data_page(channel, 71));
+        node.send_acknowledged(channel, request.data.unwrap())
+            .unwrap();
+
+        emulator.tick();
+        let data = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(matches!(
+            data,
+            FitnessEquipmentData::CommandStatus(status) if status.target_power == Some(1000)
+        ));
+
+        let power = (0..8)
+            .find_map(|_| {
+                emulator.tick();
+                match receiver.recv_timeout(Duration::from_secs(1)).unwrap() {
+                    FitnessEquipmentData::StationaryBike(bike) => bike.instantaneous_power,
+                    _ => None,
+                }
+            })
+            .unwrap();
+        assert_eq!(power, 250);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod simulator;
+
 use std::time::Duration;
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
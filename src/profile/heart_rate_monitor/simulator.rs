@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use super::HeartRateMonitorPeriod;
+use crate::device::{DataProcessor, Error, Transmitter};
+use crate::message;
+
+/// Number of main data pages sent between each run of background pages.
+const MAIN_PAGES: u32 = 64;
+/// Number of times each page is repeated before the page toggle bit changes, and the number of
+/// times a background page is sent in each run.
+const PAGE_REPEATS: u32 = 4;
+
+/// Source of the heart rate in beats per minute reported by a simulated heart rate monitor. A
+/// heart rate of 0 means no heartbeats are detected.
+pub enum HeartRateSource {
+    Constant(u8),
+    /// Heart rates used in turn for each page sent, holding the last once all have been used
+    Scripted(Vec<u8>),
+    /// Function of the time since the simulator started
+    Function(Box<dyn FnMut(Duration) -> u8 + Send>),
+}
+
+impl HeartRateSource {
+    fn heart_rate(&mut self, page: usize, elapsed: Duration) -> u8 {
+        match self {
+            HeartRateSource::Constant(heart_rate) => *heart_rate,
+            HeartRateSource::Scripted(heart_rates) => heart_rates
+                .get(page)
+                .or(heart_rates.last())
+                .copied()
+                .unwrap_or(0),
+            HeartRateSource::Function(f) => f(elapsed),
+        }
+    }
+}
+
+/// Manufacturer and product details reported in background pages 2 and 3.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProductInfo {
+    pub manufacturer_id: u8,
+    /// Upper 16 bits of the device's serial number
+    pub serial_number: u16,
+    pub hardware_version: u8,
+    pub software_version: u8,
+    pub model_number: u8,
+}
+
+/// Simulated heart rate monitor, transmitting pages that can be decoded by
+/// [`super::HeartRateMonitor`].
+///
+/// Time is simulated from the number of pages sent and the channel period, rather than the
+/// system clock, so pages are deterministic. Main data page 4 is sent, interleaved every 64
+/// pages with a background page 1, 2 or 3 in turn.
+pub struct HeartRateMonitorSimulator {
+    source: HeartRateSource,
+    period: HeartRateMonitorPeriod,
+    product: ProductInfo,
+
+    pages_sent: u32,
+    heart_rate: u8,
+    /// Time since the simulator started, in 1/1024 seconds
+    time: f64,
+    /// Time of the last heartbeat, in 1/1024 seconds
+    last_heartbeat: f64,
+    heartbeat_count: u8,
+    heartbeat_event_time: u16,
+    previous_heartbeat_event_time: u16,
+}
+
+impl HeartRateMonitorSimulator {
+    pub fn new(source: HeartRateSource) -> HeartRateMonitorSimulator {
+        HeartRateMonitorSimulator {
+            source,
+            period: HeartRateMonitorPeriod::Period4Hz,
+            product: ProductInfo::default(),
+
+            pages_sent: 0,
+            heart_rate: 0,
+            time: 0.0,
+            last_heartbeat: 0.0,
+            heartbeat_count: 0,
+            heartbeat_event_time: 0,
+            previous_heartbeat_event_time: 0,
+        }
+    }
+
+    pub fn set_channel_period(&mut self, period: u16) -> Result<(), Error> {
+        self.period = period.try_into()?;
+        Ok(())
+    }
+
+    pub fn set_product_info(&mut self, product: ProductInfo) {
+        self.product = product;
+    }
+
+    /// Advance the simulation by one channel period, adding any heartbeats that occurred.
+    fn advance(&mut self) {
+        let period: u16 = self.period.into();
+        self.time += f64::from(period) / 32.0;
+
+        let elapsed = Duration::from_secs_f64(self.time / 1024.0);
+        self.heart_rate = self.source.heart_rate(self.pages_sent as usize, elapsed);
+
+        if self.heart_rate == 0 {
+            // Heartbeats resume a full interval after the heart rate does
+            self.last_heartbeat = self.time;
+            return;
+        }
+
+        let interval = 60.0 * 1024.0 / f64::from(self.heart_rate);
+        while self.last_heartbeat + interval <= self.time {
+            self.last_heartbeat += interval;
+            self.heartbeat_count = self.heartbeat_count.wrapping_add(1);
+            self.previous_heartbeat_event_time = self.heartbeat_event_time;
+            self.heartbeat_event_time = (self.last_heartbeat as u64 % 0x10000) as u16;
+        }
+    }
+
+    fn page_number(&self) -> u8 {
+        let cycle = self.pages_sent % (MAIN_PAGES + PAGE_REPEATS);
+        if cycle < MAIN_PAGES {
+            4
+        } else {
+            let run = self.pages_sent / (MAIN_PAGES + PAGE_REPEATS);
+            (run % 3) as u8 + 1
+        }
+    }
+}
+
+impl Transmitter for HeartRateMonitorSimulator {
+    fn device_type(&self) -> u8 {
+        120
+    }
+
+    fn transmission_type(&self) -> u8 {
+        1
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        self.period.into()
+    }
+
+    fn next_page(&mut self) -> [u8; 8] {
+        self.advance();
+
+        let page = self.page_number();
+        let toggle = if (self.pages_sent / PAGE_REPEATS) % 2 == 1 {
+            0x80
+        } else {
+            0
+        };
+
+        let [b1, b2, b3] = match page {
+            1 => {
+                // Cumulative operating time in units of 2 seconds
+                let [b1, b2, b3, _] = ((self.time / 2048.0) as u32).to_le_bytes();
+                [b1, b2, b3]
+            }
+            2 => {
+                let [serial_lsb, serial_msb] = self.product.serial_number.to_le_bytes();
+                [self.product.manufacturer_id, serial_lsb, serial_msb]
+            }
+            3 => [
+                self.product.hardware_version,
+                self.product.software_version,
+                self.product.model_number,
+            ],
+            _ => {
+                let [time_lsb, time_msb] = self.previous_heartbeat_event_time.to_le_bytes();
+                [0xff, time_lsb, time_msb]
+            }
+        };
+        let [event_time_lsb, event_time_msb] = self.heartbeat_event_time.to_le_bytes();
+
+        self.pages_sent += 1;
+
+        [
+            page | toggle,
+            b1,
+            b2,
+            b3,
+            event_time_lsb,
+            event_time_msb,
+            self.heartbeat_count,
+            self.heart_rate,
+        ]
+    }
+}
+
+impl DataProcessor for HeartRateMonitorSimulator {
+    fn process_data(&mut self, _data: message::DataPayload) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::heart_rate_monitor::{new_search, HeartRateMonitorData};
+    use crate::profile::test::payload;
+
+    fn round_trip(
+        simulator: &mut HeartRateMonitorSimulator,
+        pages: usize,
+    ) -> Vec<HeartRateMonitorData> {
+        let (mut hrm, receiver) = new_search();
+        for _ in 0..pages {
+            hrm.process_data(payload(simulator.next_page())).unwrap();
+        }
+        receiver.try_iter().collect()
+    }
+
+    #[test]
+    fn it_generates_heartbeats_at_constant_heart_rate() {
+        let mut simulator = HeartRateMonitorSimulator::new(HeartRateSource::Constant(60));
+        let data = round_trip(&mut simulator, 300);
+
+        // 300 pages at 8070/32768s is 73.9s, so 73 heartbeats at 1024/1024s intervals
+        let last = data.last().unwrap();
+        assert_eq!(last.computed_heart_rate, 60);
+        assert_eq!(last.heartbeat_count, 73);
+        assert_eq!(last.heartbeat_event_time, ((73 * 1024) % 0x10000) as u16);
+
+        let previous = data.iter().rev().find(|d| d.page == 4).unwrap();
+        assert_eq!(
+            previous.previous_heartbeat_event_time,
+            Some(((72 * 1024) % 0x10000) as u16)
+        );
+    }
+
+    #[test]
+    fn it_rotates_background_pages() {
+        let mut simulator = HeartRateMonitorSimulator::new(HeartRateSource::Constant(120));
+        simulator.set_product_info(ProductInfo {
+            manufacturer_id: 1,
+            serial_number: 40,
+            hardware_version: 4,
+            software_version: 21,
+            model_number: 7,
+        });
+        let data = round_trip(&mut simulator, 3 * 68);
+
+        let pages: Vec<u8> = data.iter().map(|d| d.page).collect();
+        assert_eq!(pages[..64], [4; 64]);
+        assert_eq!(pages[64..68], [1; 4]);
+        assert_eq!(pages[132..136], [2; 4]);
+        assert_eq!(pages[200..204], [3; 4]);
+
+        assert!(data[64].cumulative_operating_time.is_some());
+        assert_eq!(data[132].manufacturer_id, Some(1));
+        assert_eq!(data[132].serial_number, Some(40));
+        assert_eq!(data[200].hardware_version, Some(4));
+        assert_eq!(data[200].software_version, Some(21));
+        assert_eq!(data[200].model_number, Some(7));
+    }
+
+    #[test]
+    fn it_follows_scripted_heart_rate() {
+        let mut simulator =
+            HeartRateMonitorSimulator::new(HeartRateSource::Scripted(vec![0, 0, 0, 0, 0, 0, 240]));
+        let data = round_trip(&mut simulator, 10);
+
+        let heart_rates: Vec<u8> = data.iter().map(|d| d.computed_heart_rate).collect();
+        assert_eq!(heart_rates, vec![0, 0, 0, 0, 0, 0, 240, 240, 240, 240]);
+        // No heartbeats while the heart rate is 0, then one every 256/1024s
+        assert_eq!(data[6].heartbeat_count, 0);
+        assert_eq!(data[9].heartbeat_count, 3);
+    }
+
+    #[test]
+    fn it_uses_heart_rate_function() {
+        let mut simulator =
+            HeartRateMonitorSimulator::new(HeartRateSource::Function(Box::new(|elapsed| {
+                if elapsed < Duration::from_secs(1) {
+                    100
+                } else {
+                    150
+                }
+            })));
+        let data = round_trip(&mut simulator, 8);
+
+        assert_eq!(data[0].computed_heart_rate, 100);
+        assert_eq!(data[7].computed_heart_rate, 150);
+    }
+}
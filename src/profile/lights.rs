@@ -0,0 +1,475 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    LightState = 1,
+    LightControl = 16,
+    Connect = 32,
+    Disconnect = 33,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum LightMode {
+    Off = 0,
+    Steady = 1,
+    SlowFlash = 2,
+    FastFlash = 3,
+    RandomFlash = 4,
+    Auto = 5,
+}
+
+/// Light index used to address every light in a network
+pub const ALL_LIGHTS: u8 = 0;
+/// Highest light index that can be assigned to a light
+pub const MAX_LIGHT_INDEX: u8 = 63;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightControl {
+    pub mode: LightMode,
+    /// Percentage up to 100, or None for the light's default intensity
+    pub intensity: Option<u8>,
+    pub high_beam: bool,
+}
+
+/// Create a connect message, assigning a light index to the light on the channel.
+pub fn connect_message(channel: u8, light_index: u8, controller_id: u16) -> message::Message {
+    let [controller_lsb, controller_msb] = controller_id.to_le_bytes();
+
+    message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([
+            PageNumber::Connect.into(),
+            light_index,
+            controller_lsb,
+            controller_msb,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+        ]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    })
+}
+
+/// Create a disconnect message, releasing the light from the network.
+pub fn disconnect_message(channel: u8, light_index: u8) -> message::Message {
+    message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([
+            PageNumber::Disconnect.into(),
+            light_index,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+        ]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    })
+}
+
+/// Create a light control message for the light index, or `ALL_LIGHTS`.
+///
+/// The sequence number is reported back by lights in their light state once the command has been
+/// applied. Fails with `Error::InvalidValue` if the intensity is over 100%.
+pub fn light_control_message(
+    channel: u8,
+    light_index: u8,
+    sequence: u8,
+    control: LightControl,
+) -> Result<message::Message, Error> {
+    if control.intensity.is_some_and(|intensity| intensity > 100) {
+        return Err(Error::InvalidValue);
+    }
+
+    Ok(message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([
+            PageNumber::LightControl.into(),
+            light_index,
+            sequence,
+            u8::from(control.mode) | u8::from(control.high_beam) << 6,
+            control.intensity.unwrap_or(0xff),
+            0xff,
+            0xff,
+            0xff,
+        ]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    }))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NetworkLight {
+    channel: u8,
+    light_index: u8,
+    connected: bool,
+    /// Sequence number of the last command the light reported applying
+    sequence: u8,
+}
+
+/// Network of lights, each on its own channel, controlled together.
+///
+/// Lights are connected by sending them the connect message with their light index. The handshake
+/// completes once the light reports that index in its light state. Likewise, a light has applied a
+/// light control command once it reports the command's sequence number.
+#[derive(Clone, Debug)]
+pub struct LightNetwork {
+    controller_id: u16,
+    sequence: u8,
+    lights: Vec<NetworkLight>,
+}
+
+impl LightNetwork {
+    pub fn new(controller_id: u16) -> LightNetwork {
+        LightNetwork {
+            controller_id,
+            sequence: 0,
+            lights: Vec::new(),
+        }
+    }
+
+    /// Add the light assigned to the channel, returning the connect message to send to it.
+    pub fn add(&mut self, channel: u8) -> Result<message::Message, Error> {
+        let light_index = match self.lights.iter().find(|light| light.channel == channel) {
+            Some(light) => light.light_index,
+            None => {
+                let light_index = (1..=MAX_LIGHT_INDEX)
+                    .find(|index| self.lights.iter().all(|light| light.light_index != *index))
+                    .ok_or(Error::InvalidValue)?;
+                self.lights.push(NetworkLight {
+                    channel,
+                    light_index,
+                    connected: false,
+                    sequence: 0,
+                });
+                light_index
+            }
+        };
+
+        Ok(connect_message(channel, light_index, self.controller_id))
+    }
+
+    /// Remove the light assigned to the channel, returning the disconnect message to send to it.
+    pub fn remove(&mut self, channel: u8) -> Option<message::Message> {
+        let position = self
+            .lights
+            .iter()
+            .position(|light| light.channel == channel)?;
+        let light = self.lights.remove(position);
+
+        Some(disconnect_message(channel, light.light_index))
+    }
+
+    /// Update the handshake state of the light on the channel, and the last command it applied,
+    /// from its data.
+    pub fn update(&mut self, channel: u8, data: &LightsData) {
+        if let LightsData::LightState(state) = data {
+            if let Some(light) = self
+                .lights
+                .iter_mut()
+                .find(|light| light.channel == channel)
+            {
+                light.connected = state.light_index == light.light_index;
+                light.sequence = state.sequence;
+            }
+        }
+    }
+
+    pub fn light_index(&self, channel: u8) -> Option<u8> {
+        self.lights
+            .iter()
+            .find(|light| light.channel == channel)
+            .map(|light| light.light_index)
+    }
+
+    pub fn is_connected(&self, channel: u8) -> bool {
+        self.lights
+            .iter()
+            .any(|light| light.channel == channel && light.connected)
+    }
+
+    /// Whether the light on the channel is connected and has applied the last light control
+    /// command, see [`LightNetwork::sequence`]. Always false before any command, as lights
+    /// report sequence 0 until they receive one.
+    pub fn is_applied(&self, channel: u8) -> bool {
+        self.sequence != 0
+            && self.lights.iter().any(|light| {
+                light.channel == channel && light.connected && light.sequence == self.sequence
+            })
+    }
+
+    /// Sequence number of the last light control command, or 0 before any command.
+    pub fn sequence(&self) -> u8 {
+        self.sequence
+    }
+
+    /// Light control messages for every connected light, sharing a sequence number. The sequence
+    /// number is only advanced when there are connected lights to send the command to.
+    pub fn control_messages(
+        &mut self,
+        control: LightControl,
+    ) -> Result<Vec<message::Message>, Error> {
+        let connected: Vec<&NetworkLight> =
+            self.lights.iter().filter(|light| light.connected).collect();
+        if connected.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // 0 is reported by lights that haven't applied a command yet
+        let sequence = match self.sequence.wrapping_add(1) {
+            0 => 1,
+            sequence => sequence,
+        };
+        let messages = connected
+            .iter()
+            .map(|light| light_control_message(light.channel, light.light_index, sequence, control))
+            .collect::<Result<_, _>>()?;
+        self.sequence = sequence;
+
+        Ok(messages)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Lights {
+    pairing: DevicePairing,
+
+    sender: crossbeam_channel::Sender<LightsData>,
+}
+
+pub fn new_search() -> (Lights, crossbeam_channel::Receiver<LightsData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(pairing: DevicePairing) -> (Lights, crossbeam_channel::Receiver<LightsData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    (Lights { pairing, sender }, receiver)
+}
+
+impl Device for Lights {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        35
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        4084
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for Lights {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::LightState) => LightsData::LightState(LightState {
+                    light_index: data[1] & 0x3f,
+                    sequence: data[2],
+                    mode: (data[3] & 0x3f).try_into()?,
+                    high_beam: data[3] & 0x40 != 0,
+                    intensity: match data[4] {
+                        0xff => None,
+                        intensity => Some(intensity),
+                    },
+                }),
+                Ok(PageNumber::LightControl | PageNumber::Connect | PageNumber::Disconnect) => {
+                    // Commands sent by another controller
+                    return Ok(());
+                }
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => LightsData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightState {
+    /// Light index assigned by the controller, or 0 when not connected
+    pub light_index: u8,
+    /// Sequence number of the last light control command applied
+    pub sequence: u8,
+    pub mode: LightMode,
+    pub high_beam: bool,
+    /// Percentage
+    pub intensity: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightsData {
+    LightState(LightState),
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::profile::test::payload;
+
+    #[test]
+    fn it_processes_light_state_and_battery_pages() {
+        let (mut lights, receiver) = new_search();
+        assert_eq!(
+            lights.process_data(payload([1, 2, 7, 0x43, 80, 0xff, 0xff, 0xff])),
+            Ok(())
+        );
+        // 3.5V, good battery, 32 seconds of operating time
+        assert_eq!(
+            lights.process_data(payload([82, 0xff, 0xff, 16, 0, 0, 0x80, 0xa3])),
+            Ok(())
+        );
+
+        let data: Vec<LightsData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                LightsData::LightState(LightState {
+                    light_index: 2,
+                    sequence: 7,
+                    mode: LightMode::FastFlash,
+                    high_beam: true,
+                    intensity: Some(80),
+                }),
+                LightsData::Common(message::common::DataPage::BatteryStatus {
+                    number_of_batteries: None,
+                    battery_identifier: None,
+                    cumulative_operating_time: Duration::from_secs(32),
+                    battery_voltage: Some(3.5),
+                    battery_status: message::common::BatteryStatus::Good,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_connects_and_controls_light_network() {
+        let mut network = LightNetwork::new(0x1234);
+        assert_eq!(
+            network.add(3),
+            Ok(message::Message::AcknowledgedData(message::DataPayload {
+                channel: 3,
+                data: Some([32, 1, 0x34, 0x12, 0xff, 0xff, 0xff, 0xff]),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            }))
+        );
+        assert!(network.add(4).is_ok());
+        assert_eq!(network.light_index(4), Some(2));
+
+        let control = LightControl {
+            mode: LightMode::Steady,
+            intensity: Some(100),
+            high_beam: false,
+        };
+        assert_eq!(network.control_messages(control), Ok(vec![]));
+        assert_eq!(network.sequence(), 0);
+
+        let state = LightState {
+            light_index: 1,
+            sequence: 0,
+            mode: LightMode::Off,
+            high_beam: false,
+            intensity: None,
+        };
+        network.update(3, &LightsData::LightState(state));
+        // light on channel 4 hasn't taken its light index yet
+        network.update(4, &LightsData::LightState(state));
+        assert!(network.is_connected(3));
+        assert!(!network.is_connected(4));
+
+        assert_eq!(
+            network.control_messages(control),
+            Ok(vec![light_control_message(3, 1, 1, control).unwrap()])
+        );
+        assert_eq!(network.sequence(), 1);
+
+        assert_eq!(network.remove(3), Some(disconnect_message(3, 1)));
+        assert!(network.add(5).is_ok());
+        assert_eq!(network.light_index(5), Some(1));
+    }
+
+    #[test]
+    fn it_checks_light_control_commands_are_applied() {
+        let mut network = LightNetwork::new(0x1234);
+        assert!(network.add(3).is_ok());
+        assert!(network.add(4).is_ok());
+
+        let state = |light_index, sequence| {
+            LightsData::LightState(LightState {
+                light_index,
+                sequence,
+                mode: LightMode::Steady,
+                high_beam: false,
+                intensity: None,
+            })
+        };
+        network.update(3, &state(1, 0));
+        network.update(4, &state(2, 0));
+        assert!(!network.is_applied(3));
+
+        let control = LightControl {
+            mode: LightMode::SlowFlash,
+            intensity: Some(50),
+            high_beam: false,
+        };
+        assert_eq!(network.control_messages(control).map(|m| m.len()), Ok(2));
+        assert!(!network.is_applied(3));
+        assert!(!network.is_applied(4));
+
+        // light on channel 4 never applies the command
+        network.update(3, &state(1, 1));
+        network.update(4, &state(2, 0));
+        assert!(network.is_applied(3));
+        assert!(!network.is_applied(4));
+
+        // intensity over 100% is rejected, without advancing the sequence
+        let invalid = LightControl {
+            intensity: Some(101),
+            ..control
+        };
+        assert_eq!(network.control_messages(invalid), Err(Error::InvalidValue));
+        assert_eq!(network.sequence(), 1);
+    }
+}
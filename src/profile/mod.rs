@@ -1,2 +1,28 @@
+pub mod bicycle_power;
+pub mod bike_speed_cadence;
+pub mod controls;
+pub mod environment;
 pub mod fitness_equipment;
 pub mod heart_rate_monitor;
+pub mod lights;
+pub mod muscle_oxygen;
+pub mod radar;
+pub mod shifting;
+pub mod stride_speed_distance;
+pub mod weight_scale;
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::message;
+
+    /// Data payload received on channel 0, without extended data.
+    pub(crate) fn payload(data: [u8; 8]) -> message::DataPayload {
+        message::DataPayload {
+            channel: 0,
+            data: Some(data),
+            channel_id: None,
+            rssi: None,
+            rx_timestamp: None,
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    MuscleOxygen = 1,
+    Commands = 16,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum Command {
+    SetTime = 0,
+    StartSession = 1,
+    StopSession = 2,
+    Lap = 3,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum MeasurementInterval {
+    Interval0_25s = 1,
+    Interval0_5s = 2,
+    Interval1s = 3,
+    Interval2s = 4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Measurement {
+    Value(f64),
+    AmbientLightTooHigh,
+    Invalid,
+}
+
+impl Measurement {
+    /// Decode a measurement of `bits` bits in increments of 1/`scale`, where the two highest
+    /// values are reserved for ambient light being too high, and invalid.
+    fn decode(value: u16, bits: u32, scale: f64) -> Measurement {
+        let invalid = (1 << bits) - 1;
+        match value {
+            v if v == invalid => Measurement::Invalid,
+            v if v == invalid - 1 => Measurement::AmbientLightTooHigh,
+            v => Measurement::Value(f64::from(v) / scale),
+        }
+    }
+}
+
+/// Create a command message.
+///
+/// The timestamp is UTC seconds since 00:00 December 31 1989, and the local time offset is in 15
+/// minute increments. Displays should send the time before starting a session.
+pub fn command_message(
+    channel: u8,
+    command: Command,
+    local_time_offset: Option<i8>,
+    timestamp: u32,
+) -> message::Message {
+    let timestamp = timestamp.to_le_bytes();
+
+    message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([
+            PageNumber::Commands.into(),
+            command.into(),
+            0xff,
+            local_time_offset.map_or(0x7f, |offset| offset as u8),
+            timestamp[0],
+            timestamp[1],
+            timestamp[2],
+            timestamp[3],
+        ]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct MuscleOxygen {
+    pairing: DevicePairing,
+
+    sender: crossbeam_channel::Sender<MuscleOxygenData>,
+}
+
+pub fn new_search() -> (MuscleOxygen, crossbeam_channel::Receiver<MuscleOxygenData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(
+    pairing: DevicePairing,
+) -> (MuscleOxygen, crossbeam_channel::Receiver<MuscleOxygenData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    (MuscleOxygen { pairing, sender }, receiver)
+}
+
+impl Device for MuscleOxygen {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        31
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8192
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for MuscleOxygen {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::MuscleOxygen) => {
+                    let measurements = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+
+                    MuscleOxygenData::MuscleOxygen {
+                        event_count: data[1],
+                        utc_time_required: data[2] & 0x01 != 0,
+                        ant_fs_supported: data[3] & 0x01 != 0,
+                        measurement_interval: ((data[3] >> 1) & 0x07).try_into()?,
+                        total_hemoglobin: Measurement::decode(
+                            (measurements & 0xfff) as u16,
+                            12,
+                            100.0,
+                        ),
+                        previous_saturated_hemoglobin: Measurement::decode(
+                            ((measurements >> 12) & 0x3ff) as u16,
+                            10,
+                            10.0,
+                        ),
+                        current_saturated_hemoglobin: Measurement::decode(
+                            (measurements >> 22) as u16,
+                            10,
+                            10.0,
+                        ),
+                    }
+                }
+                Ok(PageNumber::Commands) => MuscleOxygenData::Command {
+                    command: data[1].try_into()?,
+                    local_time_offset: match data[3] {
+                        0x7f => None,
+                        offset => Some(offset as i8),
+                    },
+                    timestamp: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+                },
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => MuscleOxygenData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MuscleOxygenData {
+    MuscleOxygen {
+        event_count: u8,
+        /// The sensor requests the display to send the time, with `command_message`
+        utc_time_required: bool,
+        ant_fs_supported: bool,
+        measurement_interval: MeasurementInterval,
+        /// measured in g/dL
+        total_hemoglobin: Measurement,
+        /// Percentage of saturated hemoglobin at the previous measurement
+        previous_saturated_hemoglobin: Measurement,
+        /// Percentage of saturated hemoglobin
+        current_saturated_hemoglobin: Measurement,
+    },
+    /// Command sent by another display
+    Command {
+        command: Command,
+        local_time_offset: Option<i8>,
+        timestamp: u32,
+    },
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::test::payload;
+
+    #[test]
+    fn it_processes_muscle_oxygen_page() {
+        let (mut moxy, receiver) = new_search();
+        // total hemoglobin of 12.5g/dL, previous saturation of 65.3% and current of 65.9%
+        let measurements: u32 = 1250 | 653 << 12 | 659 << 22;
+        let [m0, m1, m2, m3] = measurements.to_le_bytes();
+        assert_eq!(
+            moxy.process_data(payload([1, 42, 0x01, 0b0000_0110, m0, m1, m2, m3])),
+            Ok(())
+        );
+        // ambient light too high, and invalid measurements
+        let measurements: u32 = 0xffe | 0x3fe << 12 | 0x3ff << 22;
+        let [m0, m1, m2, m3] = measurements.to_le_bytes();
+        assert_eq!(
+            moxy.process_data(payload([1, 43, 0x00, 0b0000_0111, m0, m1, m2, m3])),
+            Ok(())
+        );
+
+        let data: Vec<MuscleOxygenData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                MuscleOxygenData::MuscleOxygen {
+                    event_count: 42,
+                    utc_time_required: true,
+                    ant_fs_supported: false,
+                    measurement_interval: MeasurementInterval::Interval1s,
+                    total_hemoglobin: Measurement::Value(12.5),
+                    previous_saturated_hemoglobin: Measurement::Value(65.3),
+                    current_saturated_hemoglobin: Measurement::Value(65.9),
+                },
+                MuscleOxygenData::MuscleOxygen {
+                    event_count: 43,
+                    utc_time_required: false,
+                    ant_fs_supported: true,
+                    measurement_interval: MeasurementInterval::Interval1s,
+                    total_hemoglobin: Measurement::AmbientLightTooHigh,
+                    previous_saturated_hemoglobin: Measurement::AmbientLightTooHigh,
+                    current_saturated_hemoglobin: Measurement::Invalid,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_creates_command_messages() {
+        assert_eq!(
+            command_message(3, Command::StartSession, Some(-20), 0x12345678),
+            message::Message::AcknowledgedData(message::DataPayload {
+                channel: 3,
+                data: Some([16, 1, 0xff, 0xec, 0x78, 0x56, 0x34, 0x12]),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            })
+        );
+
+        let (mut moxy, receiver) = new_search();
+        let message::Message::AcknowledgedData(lap) = command_message(0, Command::Lap, None, 100)
+        else {
+            panic!("expected acknowledged data");
+        };
+        assert_eq!(moxy.process_data(lap), Ok(()));
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            MuscleOxygenData::Command {
+                command: Command::Lap,
+                local_time_offset: None,
+                timestamp: 100,
+            }
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    Disable = 2,
+    Shutdown = 3,
+    TargetsA = 48,
+    TargetsB = 49,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum ThreatLevel {
+    #[default]
+    NoThreat = 0,
+    VehicleApproaching = 1,
+    VehicleFastApproaching = 2,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum ThreatSide {
+    #[default]
+    NoSide = 0,
+    Right = 1,
+    Left = 2,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Target {
+    pub threat_level: ThreatLevel,
+    pub threat_side: ThreatSide,
+    /// measured in metres, in 3.125m increments
+    pub range: f64,
+    /// measured in m/s, in 3.04m/s increments
+    pub closing_speed: f64,
+}
+
+/// Targets reported by each page
+const TARGETS_PER_PAGE: usize = 4;
+
+/// Create a command message temporarily disabling, or re-enabling the radar's transmissions.
+pub fn disable_message(channel: u8, disabled: bool) -> message::Message {
+    command_message(channel, PageNumber::Disable, disabled.into())
+}
+
+/// Create a command message to shut down the radar.
+pub fn shutdown_message(channel: u8) -> message::Message {
+    command_message(channel, PageNumber::Shutdown, 0xff)
+}
+
+fn command_message(channel: u8, page: PageNumber, value: u8) -> message::Message {
+    message::Message::AcknowledgedData(message::DataPayload {
+        channel,
+        data: Some([page.into(), value, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+        channel_id: None,
+        rssi: None,
+        rx_timestamp: None,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Radar {
+    pairing: DevicePairing,
+
+    targets: [Target; 2 * TARGETS_PER_PAGE],
+
+    sender: crossbeam_channel::Sender<RadarData>,
+}
+
+pub fn new_search() -> (Radar, crossbeam_channel::Receiver<RadarData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(pairing: DevicePairing) -> (Radar, crossbeam_channel::Receiver<RadarData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    let radar = Radar {
+        pairing,
+
+        targets: Default::default(),
+
+        sender,
+    };
+
+    (radar, receiver)
+}
+
+impl Radar {
+    /// Update the targets reported by a page, starting from the given target index.
+    fn process_targets(&mut self, first: usize, data: [u8; 8]) -> Result<(), Error> {
+        let ranges = u32::from_le_bytes([data[3], data[4], data[5], 0]);
+        let closing_speeds = u16::from_le_bytes([data[6], data[7]]);
+
+        for i in 0..TARGETS_PER_PAGE {
+            self.targets[first + i] = Target {
+                threat_level: ((data[1] >> (2 * i)) & 0x03).try_into()?,
+                threat_side: ((data[2] >> (2 * i)) & 0x03).try_into()?,
+                range: f64::from((ranges >> (6 * i)) & 0x3f) * 3.125,
+                closing_speed: f64::from((closing_speeds >> (4 * i)) & 0x0f) * 3.04,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl Device for Radar {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        40
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        4084
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for Radar {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::TargetsA) => {
+                    self.process_targets(0, data)?;
+                    RadarData::Targets(self.targets)
+                }
+                Ok(PageNumber::TargetsB) => {
+                    self.process_targets(TARGETS_PER_PAGE, data)?;
+                    RadarData::Targets(self.targets)
+                }
+                Ok(PageNumber::Disable) => RadarData::Disable {
+                    disabled: data[1] & 0x01 != 0,
+                },
+                Ok(PageNumber::Shutdown) => RadarData::Shutdown,
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => RadarData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RadarData {
+    /// All eight targets, where targets without a threat are not present
+    Targets([Target; 2 * TARGETS_PER_PAGE]),
+    /// Disable command sent by another display
+    Disable {
+        disabled: bool,
+    },
+    /// Shutdown command sent by another display
+    Shutdown,
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::test::payload;
+
+    #[test]
+    fn it_processes_target_pages() {
+        let (mut radar, receiver) = new_search();
+        // target 1 approaching on the right at 25m and 6.08m/s, target 2 fast approaching on the
+        // left at 50m and 12.16m/s
+        let ranges: u32 = 8 | 16 << 6;
+        let [r0, r1, r2, _] = ranges.to_le_bytes();
+        assert_eq!(
+            radar.process_data(payload([48, 0b1001, 0b1001, r0, r1, r2, 0x42, 0x00])),
+            Ok(())
+        );
+        // target 5 approaching with no side at 100m
+        assert_eq!(
+            radar.process_data(payload([49, 0b01, 0b00, 32, 0, 0, 0x01, 0x00])),
+            Ok(())
+        );
+
+        let data: Vec<RadarData> = receiver.try_iter().collect();
+        let RadarData::Targets(targets) = data[1] else {
+            panic!("expected targets");
+        };
+        assert_eq!(
+            targets[0],
+            Target {
+                threat_level: ThreatLevel::VehicleApproaching,
+                threat_side: ThreatSide::Right,
+                range: 25.0,
+                closing_speed: 6.08,
+            }
+        );
+        assert_eq!(
+            targets[1],
+            Target {
+                threat_level: ThreatLevel::VehicleFastApproaching,
+                threat_side: ThreatSide::Left,
+                range: 50.0,
+                closing_speed: 12.16,
+            }
+        );
+        assert_eq!(targets[2], Target::default());
+        assert_eq!(
+            targets[4],
+            Target {
+                threat_level: ThreatLevel::VehicleApproaching,
+                threat_side: ThreatSide::NoSide,
+                range: 100.0,
+                closing_speed: 3.04,
+            }
+        );
+    }
+
+    #[test]
+    fn it_creates_command_messages() {
+        let (mut radar, receiver) = new_search();
+        for message in [disable_message(0, true), shutdown_message(0)] {
+            let message::Message::AcknowledgedData(payload) = message else {
+                panic!("expected acknowledged data");
+            };
+            assert_eq!(radar.process_data(payload), Ok(()));
+        }
+
+        let data: Vec<RadarData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![RadarData::Disable { disabled: true }, RadarData::Shutdown]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    ShiftSystemStatus = 1,
+}
+
+#[derive(Clone, Debug)]
+pub struct Shifting {
+    pairing: DevicePairing,
+
+    sender: crossbeam_channel::Sender<ShiftingData>,
+}
+
+pub fn new_search() -> (Shifting, crossbeam_channel::Receiver<ShiftingData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(pairing: DevicePairing) -> (Shifting, crossbeam_channel::Receiver<ShiftingData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    (Shifting { pairing, sender }, receiver)
+}
+
+/// Decode a gear, where the highest value of the field is invalid.
+fn gear(value: u8, invalid: u8) -> Option<u8> {
+    (value != invalid).then_some(value)
+}
+
+impl Device for Shifting {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        34
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8192
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for Shifting {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::ShiftSystemStatus) => {
+                    ShiftingData::ShiftSystemStatus(ShiftSystemStatus {
+                        event_count: data[1],
+                        rear_gear: gear(data[3] & 0x1f, 0x1f),
+                        front_gear: gear(data[3] >> 5, 0x07),
+                        total_rear_gears: gear(data[4] & 0x1f, 0x1f),
+                        total_front_gears: gear(data[4] >> 5, 0x07),
+                        invalid_inboard_shifts_rear: data[5] & 0x0f,
+                        invalid_outboard_shifts_rear: data[5] >> 4,
+                        invalid_inboard_shifts_front: data[6] & 0x0f,
+                        invalid_outboard_shifts_front: data[6] >> 4,
+                        shift_failures_rear: data[7] & 0x0f,
+                        shift_failures_front: data[7] >> 4,
+                    })
+                }
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => ShiftingData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+/// Gears are numbered from 0, the smallest sprocket or chainring. Shift counts wrap around at 16.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShiftSystemStatus {
+    pub event_count: u8,
+
+    pub rear_gear: Option<u8>,
+    pub front_gear: Option<u8>,
+    pub total_rear_gears: Option<u8>,
+    pub total_front_gears: Option<u8>,
+
+    pub invalid_inboard_shifts_rear: u8,
+    pub invalid_outboard_shifts_rear: u8,
+    pub invalid_inboard_shifts_front: u8,
+    pub invalid_outboard_shifts_front: u8,
+    pub shift_failures_rear: u8,
+    pub shift_failures_front: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShiftingData {
+    ShiftSystemStatus(ShiftSystemStatus),
+    /// Includes the multi-component pages reported by each component of the system, e.g. front
+    /// and rear derailleurs and shifters
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::test::payload;
+
+    #[test]
+    fn it_processes_shift_system_status() {
+        let (mut shifting, receiver) = new_search();
+        // gear 1 of 2 at the front, gear 7 of 11 at the rear
+        assert_eq!(
+            shifting.process_data(payload([
+                1,
+                12,
+                0xff,
+                1 << 5 | 7,
+                2 << 5 | 11,
+                0x21,
+                0x00,
+                0x30,
+            ])),
+            Ok(())
+        );
+        // rear only system
+        assert_eq!(
+            shifting.process_data(payload([1, 13, 0xff, 0xe3, 0xec, 0, 0, 0])),
+            Ok(())
+        );
+
+        let data: Vec<ShiftingData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                ShiftingData::ShiftSystemStatus(ShiftSystemStatus {
+                    event_count: 12,
+                    rear_gear: Some(7),
+                    front_gear: Some(1),
+                    total_rear_gears: Some(11),
+                    total_front_gears: Some(2),
+                    invalid_inboard_shifts_rear: 1,
+                    invalid_outboard_shifts_rear: 2,
+                    invalid_inboard_shifts_front: 0,
+                    invalid_outboard_shifts_front: 0,
+                    shift_failures_rear: 0,
+                    shift_failures_front: 3,
+                }),
+                ShiftingData::ShiftSystemStatus(ShiftSystemStatus {
+                    event_count: 13,
+                    rear_gear: Some(3),
+                    front_gear: None,
+                    total_rear_gears: Some(12),
+                    total_front_gears: None,
+                    invalid_inboard_shifts_rear: 0,
+                    invalid_outboard_shifts_rear: 0,
+                    invalid_inboard_shifts_front: 0,
+                    invalid_outboard_shifts_front: 0,
+                    shift_failures_rear: 0,
+                    shift_failures_front: 0,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_processes_multi_component_pages() {
+        let (mut shifting, receiver) = new_search();
+        // rear derailleur, component 1 of 3
+        assert_eq!(
+            shifting.process_data(payload([78, 0x13, 0xff, 4, 1, 0, 0x39, 0x30])),
+            Ok(())
+        );
+        assert_eq!(
+            shifting.process_data(payload([79, 0x13, 5, 2, 0x78, 0x56, 0x34, 0x12])),
+            Ok(())
+        );
+
+        let data: Vec<ShiftingData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                ShiftingData::Common(
+                    message::common::DataPage::MultiComponentManufacturerInformation {
+                        number_of_components: 3,
+                        component_identifier: 1,
+                        hardware_revision: 4,
+                        manufacturer_id: 1,
+                        model_number: 12345,
+                    }
+                ),
+                ShiftingData::Common(
+                    message::common::DataPage::MultiComponentProductInformation {
+                        number_of_components: 3,
+                        component_identifier: 1,
+                        software_revision: 205,
+                        serial_number: 0x12345678,
+                    }
+                ),
+            ]
+        );
+    }
+}
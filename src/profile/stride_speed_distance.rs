@@ -0,0 +1,438 @@
+use num_enum::{FromPrimitive, IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    SpeedAndDistance = 1,
+    SpeedAndCadence = 2,
+    SpeedCadenceAndCalories = 3,
+    DistanceAndStridesSinceReset = 16,
+    Capabilities = 22,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum Location {
+    Laces = 0,
+    Midsole = 1,
+    Other = 2,
+    Ankle = 3,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum BatteryStatus {
+    New = 0,
+    Good = 1,
+    Ok = 2,
+    Low = 3,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, IntoPrimitive)]
+pub enum Health {
+    Ok = 0,
+    Error = 1,
+    Warning = 2,
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive, IntoPrimitive)]
+pub enum UseState {
+    Inactive = 0,
+    Active = 1,
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Status {
+    pub location: Location,
+    pub battery: BatteryStatus,
+    pub health: Health,
+    pub use_state: UseState,
+}
+
+impl TryFrom<u8> for Status {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(Status {
+            location: (value >> 6).try_into()?,
+            battery: ((value >> 4) & 0x03).try_into()?,
+            health: ((value >> 2) & 0x03).into(),
+            use_state: (value & 0x03).into(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capabilities {
+    pub time: bool,
+    pub distance: bool,
+    pub speed: bool,
+    pub latency: bool,
+    pub cadence: bool,
+    pub calories: bool,
+}
+
+impl From<u8> for Capabilities {
+    fn from(value: u8) -> Self {
+        Capabilities {
+            time: value & 0x01 != 0,
+            distance: value & 0x02 != 0,
+            speed: value & 0x04 != 0,
+            latency: value & 0x08 != 0,
+            cadence: value & 0x10 != 0,
+            calories: value & 0x20 != 0,
+        }
+    }
+}
+
+/// Distance is reported in 1/16m, rolling over at 256m
+const DISTANCE_ROLLOVER: u16 = 256 * 16;
+
+/// Stride-based speed and distance monitor (footpod) receiver.
+///
+/// The profile defines no calibration pages: footpods are calibrated by the display scaling the
+/// reported speed and distance, so sensor calibration is out of scope and left to the caller.
+#[derive(Clone, Debug)]
+pub struct StrideSpeedDistance {
+    pairing: DevicePairing,
+
+    last_distance: Option<u16>,
+    last_strides: Option<u8>,
+    total_distance: u32,
+    total_strides: u32,
+
+    sender: crossbeam_channel::Sender<StrideSpeedDistanceData>,
+}
+
+pub fn new_search() -> (
+    StrideSpeedDistance,
+    crossbeam_channel::Receiver<StrideSpeedDistanceData>,
+) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(
+    pairing: DevicePairing,
+) -> (
+    StrideSpeedDistance,
+    crossbeam_channel::Receiver<StrideSpeedDistanceData>,
+) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    let sdm = StrideSpeedDistance {
+        pairing,
+
+        last_distance: None,
+        last_strides: None,
+        total_distance: 0,
+        total_strides: 0,
+
+        sender,
+    };
+
+    (sdm, receiver)
+}
+
+impl StrideSpeedDistance {
+    /// Accumulate the rolling distance and stride count, from the first page received.
+    fn accumulate(&mut self, distance: u16, strides: u8) {
+        if let Some(last) = self.last_distance {
+            self.total_distance += u32::from(
+                distance.wrapping_sub(last).wrapping_add(DISTANCE_ROLLOVER) % DISTANCE_ROLLOVER,
+            );
+        }
+        if let Some(last) = self.last_strides {
+            self.total_strides += u32::from(strides.wrapping_sub(last));
+        }
+
+        self.last_distance = Some(distance);
+        self.last_strides = Some(strides);
+    }
+}
+
+/// Speed in m/s from the 4 bit integer and 1/256 fractional parts.
+fn speed(data: [u8; 8]) -> f64 {
+    f64::from(data[4] & 0x0f) + f64::from(data[5]) / 256.0
+}
+
+/// Cadence in strides per minute from the integer and 4 bit 1/16 fractional parts.
+fn cadence(data: [u8; 8]) -> f64 {
+    f64::from(data[3]) + f64::from(data[4] >> 4) / 16.0
+}
+
+impl Device for StrideSpeedDistance {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        124
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8134
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for StrideSpeedDistance {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::SpeedAndDistance) => {
+                    let distance = u16::from(data[3]) << 4 | u16::from(data[4] >> 4);
+                    self.accumulate(distance, data[6]);
+
+                    StrideSpeedDistanceData::SpeedAndDistance(SpeedAndDistanceData {
+                        time: f64::from(data[2]) + f64::from(data[1]) / 200.0,
+                        distance: f64::from(distance) / 16.0,
+                        instantaneous_speed: speed(data),
+                        stride_count: data[6],
+                        update_latency: f64::from(data[7]) / 32.0,
+
+                        total_distance: f64::from(self.total_distance) / 16.0,
+                        total_strides: self.total_strides,
+                    })
+                }
+                Ok(PageNumber::SpeedAndCadence) => {
+                    StrideSpeedDistanceData::SpeedAndCadence(SpeedAndCadenceData {
+                        cadence: cadence(data),
+                        instantaneous_speed: speed(data),
+                        status: data[7].try_into()?,
+                    })
+                }
+                Ok(PageNumber::SpeedCadenceAndCalories) => {
+                    StrideSpeedDistanceData::SpeedCadenceAndCalories(SpeedCadenceAndCaloriesData {
+                        cadence: cadence(data),
+                        instantaneous_speed: speed(data),
+                        calories: data[6],
+                        status: data[7].try_into()?,
+                    })
+                }
+                Ok(PageNumber::DistanceAndStridesSinceReset) => {
+                    StrideSpeedDistanceData::DistanceAndStridesSinceReset {
+                        strides: u32::from_le_bytes([data[1], data[2], data[3], 0]),
+                        distance: f64::from(u32::from_le_bytes([
+                            data[4], data[5], data[6], data[7],
+                        ])) / 256.0,
+                    }
+                }
+                Ok(PageNumber::Capabilities) => {
+                    StrideSpeedDistanceData::Capabilities(data[1].into())
+                }
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => StrideSpeedDistanceData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeedAndDistanceData {
+    /// measured in seconds, wraps around at 256s
+    pub time: f64,
+    /// measured in metres, wraps around at 256m
+    pub distance: f64,
+    /// measured in m/s
+    pub instantaneous_speed: f64,
+    /// wraps around at 256 strides
+    pub stride_count: u8,
+    /// measured in seconds
+    pub update_latency: f64,
+
+    /// Distance in metres accumulated since the first page received
+    pub total_distance: f64,
+    /// Strides accumulated since the first page received
+    pub total_strides: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeedAndCadenceData {
+    /// measured in strides per minute
+    pub cadence: f64,
+    /// measured in m/s
+    pub instantaneous_speed: f64,
+    pub status: Status,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeedCadenceAndCaloriesData {
+    /// measured in strides per minute
+    pub cadence: f64,
+    /// measured in m/s
+    pub instantaneous_speed: f64,
+    /// measured in kcal, wraps around at 256kcal
+    pub calories: u8,
+    pub status: Status,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StrideSpeedDistanceData {
+    SpeedAndDistance(SpeedAndDistanceData),
+    SpeedAndCadence(SpeedAndCadenceData),
+    SpeedCadenceAndCalories(SpeedCadenceAndCaloriesData),
+    DistanceAndStridesSinceReset {
+        strides: u32,
+        /// measured in metres
+        distance: f64,
+    },
+    Capabilities(Capabilities),
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn process(sdm: &mut StrideSpeedDistance, data: [u8; 8]) {
+        assert_eq!(
+            sdm.process_data(message::DataPayload {
+                channel: 0,
+                data: Some(data),
+                channel_id: None,
+                rssi: None,
+                rx_timestamp: None,
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn it_accumulates_distance_and_strides_across_rollovers() {
+        let (mut sdm, receiver) = new_search();
+        // 250.5m and 250 strides, at 3.5m/s
+        process(&mut sdm, [1, 100, 10, 250, 0x83, 0x80, 250, 8]);
+        // 4.25m and 4 strides, after rolling over
+        process(&mut sdm, [1, 0, 12, 4, 0x43, 0x80, 4, 8]);
+
+        let data: Vec<StrideSpeedDistanceData> = receiver.try_iter().collect();
+        assert_eq!(
+            data[0],
+            StrideSpeedDistanceData::SpeedAndDistance(SpeedAndDistanceData {
+                time: 10.5,
+                distance: 250.5,
+                instantaneous_speed: 3.5,
+                stride_count: 250,
+                update_latency: 0.25,
+                total_distance: 0.0,
+                total_strides: 0,
+            })
+        );
+        assert!(matches!(
+            data[1],
+            StrideSpeedDistanceData::SpeedAndDistance(SpeedAndDistanceData {
+                total_distance: 9.75,
+                total_strides: 10,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn it_processes_speed_and_cadence_page() {
+        let (mut sdm, receiver) = new_search();
+        process(&mut sdm, [2, 0xff, 0xff, 90, 0x83, 0x40, 0xff, 0b0101_0001]);
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            StrideSpeedDistanceData::SpeedAndCadence(SpeedAndCadenceData {
+                cadence: 90.5,
+                instantaneous_speed: 3.25,
+                status: Status {
+                    location: Location::Midsole,
+                    battery: BatteryStatus::Good,
+                    health: Health::Ok,
+                    use_state: UseState::Active,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn it_processes_reserved_status_values() {
+        let (mut sdm, receiver) = new_search();
+        process(&mut sdm, [2, 0xff, 0xff, 90, 0x83, 0x40, 0xff, 0b1000_1111]);
+
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            StrideSpeedDistanceData::SpeedAndCadence(SpeedAndCadenceData {
+                status: Status {
+                    location: Location::Other,
+                    battery: BatteryStatus::New,
+                    health: Health::Unknown(3),
+                    use_state: UseState::Unknown(3),
+                },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn it_processes_background_pages() {
+        let (mut sdm, receiver) = new_search();
+        process(&mut sdm, [16, 0x10, 0x27, 0x00, 0x00, 0x00, 0x10, 0x00]);
+        process(
+            &mut sdm,
+            [22, 0b0001_0111, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+        );
+        process(&mut sdm, [80, 0xff, 0xff, 5, 1, 0, 0x2c, 0x01]);
+
+        let data: Vec<StrideSpeedDistanceData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                StrideSpeedDistanceData::DistanceAndStridesSinceReset {
+                    strides: 10000,
+                    distance: 4096.0,
+                },
+                StrideSpeedDistanceData::Capabilities(Capabilities {
+                    time: true,
+                    distance: true,
+                    speed: true,
+                    latency: false,
+                    cadence: true,
+                    calories: false,
+                }),
+                StrideSpeedDistanceData::Common(
+                    message::common::DataPage::ManufacturerInformation {
+                        hardware_revision: 5,
+                        manufacturer_id: 1,
+                        model_number: 300,
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::device::{DataProcessor, Device, DevicePairing, Error};
+use crate::message;
+use log::warn;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum PageNumber {
+    BodyWeight = 1,
+    BodyComposition = 2,
+    MetabolicInformation = 3,
+    BodyMass = 4,
+    UserProfile = 58,
+}
+
+/// User profile ID reported by scales that haven't selected a user profile
+const NO_USER_PROFILE: u16 = 0xffff;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Measurement {
+    Value(f64),
+    /// The scale is still computing the value
+    Computing,
+    Invalid,
+}
+
+impl Measurement {
+    /// Decode a measurement in increments of 1/`scale`, where the two highest values of the field
+    /// are reserved for computing, and invalid.
+    fn decode(value: u16, invalid: u16, scale: f64) -> Measurement {
+        match value {
+            v if v == invalid => Measurement::Invalid,
+            v if v == invalid - 1 => Measurement::Computing,
+            v => Measurement::Value(f64::from(v) / scale),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capabilities {
+    /// The scale can receive user profiles from a display
+    pub user_profile_exchange: bool,
+    /// The scale has selected the user profile for the current measurement
+    pub user_profile_selected: bool,
+    pub ant_fs: bool,
+}
+
+impl From<u8> for Capabilities {
+    fn from(value: u8) -> Self {
+        Capabilities {
+            user_profile_exchange: value & 0x01 != 0,
+            user_profile_selected: value & 0x02 != 0,
+            ant_fs: value & 0x04 != 0,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
+pub enum Gender {
+    Female = 0,
+    Male = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UserProfile {
+    pub id: u16,
+    pub gender: Gender,
+    /// measured in years
+    pub age: u8,
+    /// measured in cm
+    pub height: u8,
+    /// Activity level from 0 (sedentary) to 6
+    pub activity_level: u8,
+    pub athlete: bool,
+}
+
+impl UserProfile {
+    fn encode(&self) -> [u8; 8] {
+        let [id_lsb, id_msb] = self.id.to_le_bytes();
+
+        [
+            PageNumber::UserProfile.into(),
+            id_lsb,
+            id_msb,
+            // display supports user profile exchange
+            0x80,
+            0xff,
+            self.age & 0x7f | u8::from(self.gender) << 7,
+            self.height,
+            self.activity_level & 0x07 | u8::from(self.athlete) << 7,
+        ]
+    }
+
+    fn decode(data: [u8; 8]) -> UserProfile {
+        UserProfile {
+            id: u16::from_le_bytes([data[1], data[2]]),
+            gender: if data[5] & 0x80 != 0 {
+                Gender::Male
+            } else {
+                Gender::Female
+            },
+            age: data[5] & 0x7f,
+            height: data[6],
+            activity_level: data[7] & 0x07,
+            athlete: data[7] & 0x80 != 0,
+        }
+    }
+}
+
+/// State of the user profile exchange for the scale's current request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ProfileExchange {
+    /// Waiting for the scale to request a user profile
+    Idle,
+    /// The scale requested a user profile, which is sent with the next response
+    Requested,
+    /// The user profile was sent, waiting for the scale to acknowledge it
+    Sending,
+    /// The scale acknowledged the user profile
+    Sent,
+}
+
+#[derive(Clone, Debug)]
+pub struct WeightScale {
+    pairing: DevicePairing,
+
+    user_profile: Option<UserProfile>,
+    user_profiles: crossbeam_channel::Receiver<UserProfile>,
+    user_profile_updater: crossbeam_channel::Sender<UserProfile>,
+    exchange: ProfileExchange,
+
+    sender: crossbeam_channel::Sender<WeightScaleData>,
+}
+
+pub fn new_search() -> (WeightScale, crossbeam_channel::Receiver<WeightScaleData>) {
+    new_paired(DevicePairing {
+        device_id: 0,
+        transmission_type: 0,
+    })
+}
+
+pub fn new_paired(
+    pairing: DevicePairing,
+) -> (WeightScale, crossbeam_channel::Receiver<WeightScaleData>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (user_profile_updater, user_profiles) = crossbeam_channel::unbounded();
+
+    let scale = WeightScale {
+        pairing,
+
+        user_profile: None,
+        user_profiles,
+        user_profile_updater,
+        exchange: ProfileExchange::Idle,
+
+        sender,
+    };
+
+    (scale, receiver)
+}
+
+impl WeightScale {
+    /// Sender for setting the user profile sent to scales supporting user profile exchange, so
+    /// they can compute body composition for the user. Can be used after the scale has been
+    /// assigned to a channel.
+    pub fn user_profile_updater(&self) -> crossbeam_channel::Sender<UserProfile> {
+        self.user_profile_updater.clone()
+    }
+}
+
+/// Decode the user profile ID common to the measurement pages.
+fn user_profile_id(data: [u8; 8]) -> Option<u16> {
+    match u16::from_le_bytes([data[1], data[2]]) {
+        NO_USER_PROFILE => None,
+        id => Some(id),
+    }
+}
+
+impl Device for WeightScale {
+    fn channel_type(&self) -> message::ChannelType {
+        message::ChannelType::Receive
+    }
+
+    fn device_type(&self) -> u8 {
+        119
+    }
+
+    fn rf_frequency(&self) -> u8 {
+        57
+    }
+
+    fn channel_period(&self) -> u16 {
+        8192
+    }
+
+    fn pairing(&self) -> DevicePairing {
+        self.pairing
+    }
+
+    fn as_data_processor(&self) -> Box<dyn DataProcessor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl DataProcessor for WeightScale {
+    fn process_data(&mut self, data: message::DataPayload) -> Result<(), Error> {
+        if let Some(user_profile) = self.user_profiles.try_iter().last() {
+            self.user_profile = Some(user_profile);
+        }
+
+        if let Some(data) = data.data {
+            let page = match data[0].try_into() {
+                Ok(PageNumber::BodyWeight) => {
+                    let capabilities: Capabilities = data[3].into();
+                    if capabilities.user_profile_selected {
+                        // Request answered, any later request is for a new measurement
+                        self.exchange = ProfileExchange::Idle;
+                    } else if capabilities.user_profile_exchange
+                        && self.user_profile.is_some()
+                        && self.exchange == ProfileExchange::Idle
+                    {
+                        self.exchange = ProfileExchange::Requested;
+                    }
+
+                    WeightScaleData::BodyWeight {
+                        user_profile_id: user_profile_id(data),
+                        capabilities,
+                        weight: Measurement::decode(
+                            u16::from_le_bytes([data[6], data[7]]),
+                            0xffff,
+                            100.0,
+                        ),
+                    }
+                }
+                Ok(PageNumber::BodyComposition) => WeightScaleData::BodyComposition {
+                    user_profile_id: user_profile_id(data),
+                    hydration: Measurement::decode(
+                        u16::from_le_bytes([data[4], data[5]]),
+                        0xffff,
+                        100.0,
+                    ),
+                    body_fat: Measurement::decode(
+                        u16::from_le_bytes([data[6], data[7]]),
+                        0xffff,
+                        100.0,
+                    ),
+                },
+                Ok(PageNumber::MetabolicInformation) => WeightScaleData::MetabolicInformation {
+                    user_profile_id: user_profile_id(data),
+                    active_metabolic_rate: Measurement::decode(
+                        u16::from_le_bytes([data[4], data[5]]),
+                        0xffff,
+                        4.0,
+                    ),
+                    basal_metabolic_rate: Measurement::decode(
+                        u16::from_le_bytes([data[6], data[7]]),
+                        0xffff,
+                        4.0,
+                    ),
+                },
+                Ok(PageNumber::BodyMass) => WeightScaleData::BodyMass {
+                    user_profile_id: user_profile_id(data),
+                    muscle_mass: Measurement::decode(
+                        u16::from_le_bytes([data[4], data[5]]),
+                        0xffff,
+                        100.0,
+                    ),
+                    bone_mass: Measurement::decode(u16::from(data[6]), 0xff, 10.0),
+                },
+                Ok(PageNumber::UserProfile) => {
+                    WeightScaleData::UserProfile(UserProfile::decode(data))
+                }
+                Err(_) => match message::common::decode(data) {
+                    Some(common_data) => WeightScaleData::Common(common_data),
+                    None => {
+                        warn!("received unhandled data page: {:?}", data);
+                        return Ok(());
+                    }
+                },
+            };
+
+            self.sender.try_send(page)?;
+        }
+        Ok(())
+    }
+
+    fn response(&mut self) -> Option<[u8; 8]> {
+        if self.exchange != ProfileExchange::Requested {
+            return None;
+        }
+
+        self.exchange = ProfileExchange::Sending;
+        self.user_profile.map(|profile| profile.encode())
+    }
+
+    fn process_event(&mut self, code: message::MessageCode) {
+        if self.exchange == ProfileExchange::Sending {
+            match code {
+                message::MessageCode::EventTransferTXCompleted => {
+                    self.exchange = ProfileExchange::Sent;
+                }
+                // Sent again if the scale still requests it
+                message::MessageCode::EventTransferTXFailed => {
+                    self.exchange = ProfileExchange::Idle;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeightScaleData {
+    BodyWeight {
+        user_profile_id: Option<u16>,
+        capabilities: Capabilities,
+        /// measured in kg
+        weight: Measurement,
+    },
+    /// Percentages of body weight
+    BodyComposition {
+        user_profile_id: Option<u16>,
+        hydration: Measurement,
+        body_fat: Measurement,
+    },
+    /// measured in kcal per day
+    MetabolicInformation {
+        user_profile_id: Option<u16>,
+        active_metabolic_rate: Measurement,
+        basal_metabolic_rate: Measurement,
+    },
+    /// measured in kg
+    BodyMass {
+        user_profile_id: Option<u16>,
+        muscle_mass: Measurement,
+        bone_mass: Measurement,
+    },
+    /// User profile selected by the scale
+    UserProfile(UserProfile),
+    Common(message::common::DataPage),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile::test::payload;
+
+    const USER: UserProfile = UserProfile {
+        id: 16,
+        gender: Gender::Female,
+        age: 35,
+        height: 170,
+        activity_level: 4,
+        athlete: true,
+    };
+
+    #[test]
+    fn it_processes_measurement_pages() {
+        let (mut scale, receiver) = new_search();
+        let pages = [
+            [1, 16, 0, 0x03, 0xff, 0xff, 0x58, 0x1b],
+            [2, 16, 0, 0xff, 0x70, 0x17, 0xfe, 0xff],
+            [3, 16, 0, 0xff, 0x40, 0x25, 0xff, 0xff],
+            [4, 16, 0, 0xff, 0xd0, 0x07, 25, 0xff],
+        ];
+        for page in pages {
+            assert_eq!(scale.process_data(payload(page)), Ok(()));
+        }
+
+        let data: Vec<WeightScaleData> = receiver.try_iter().collect();
+        assert_eq!(
+            data,
+            vec![
+                WeightScaleData::BodyWeight {
+                    user_profile_id: Some(16),
+                    capabilities: Capabilities {
+                        user_profile_exchange: true,
+                        user_profile_selected: true,
+                        ant_fs: false,
+                    },
+                    weight: Measurement::Value(70.0),
+                },
+                WeightScaleData::BodyComposition {
+                    user_profile_id: Some(16),
+                    hydration: Measurement::Value(60.0),
+                    body_fat: Measurement::Computing,
+                },
+                WeightScaleData::MetabolicInformation {
+                    user_profile_id: Some(16),
+                    active_metabolic_rate: Measurement::Value(2384.0),
+                    basal_metabolic_rate: Measurement::Invalid,
+                },
+                WeightScaleData::BodyMass {
+                    user_profile_id: Some(16),
+                    muscle_mass: Measurement::Value(20.0),
+                    bone_mass: Measurement::Value(2.5),
+                },
+            ]
+        );
+        assert_eq!(scale.response(), None);
+    }
+
+    #[test]
+    fn it_sends_user_profile_when_requested() {
+        let (mut scale, receiver) = new_search();
+        let unselected = [1, 0xff, 0xff, 0x01, 0xff, 0xff, 0xfe, 0xff];
+
+        // no user profile to send
+        assert_eq!(scale.process_data(payload(unselected)), Ok(()));
+        assert_eq!(scale.response(), None);
+
+        scale.user_profile_updater().send(USER).unwrap();
+        assert_eq!(scale.process_data(payload(unselected)), Ok(()));
+        let response = scale.response().unwrap();
+        assert_eq!(response, [58, 16, 0, 0x80, 0xff, 35, 170, 0x84]);
+        assert_eq!(scale.response(), None);
+
+        // sent once per request, unless the transfer fails
+        assert_eq!(scale.process_data(payload(unselected)), Ok(()));
+        assert_eq!(scale.response(), None);
+        scale.process_event(message::MessageCode::EventTransferTXFailed);
+        assert_eq!(scale.process_data(payload(unselected)), Ok(()));
+        assert_eq!(scale.response(), Some(response));
+        scale.process_event(message::MessageCode::EventTransferTXCompleted);
+        assert_eq!(scale.process_data(payload(unselected)), Ok(()));
+        assert_eq!(scale.response(), None);
+
+        assert_eq!(scale.process_data(payload(response)), Ok(()));
+        assert_eq!(
+            receiver.try_iter().last(),
+            Some(WeightScaleData::UserProfile(USER))
+        );
+    }
+}